@@ -4,7 +4,7 @@ use rand_chacha::ChaCha20Rng;
 use raytracer::{
     bvh::{
         aabb::Aabb,
-        bbox_tree::{BboxTree, BboxTreeWorkspace},
+        bbox_tree::{BboxTree, BboxTreeWorkspace, BuildOptions, SplitStrategy},
     },
     camera::{CameraBuilder, CameraPosition},
     core::{
@@ -69,7 +69,7 @@ mod bvh_builder {
             ));
             let dir = random_on_unit_sphere_distribution(rng);
             let ray = Ray::new(orig, dir);
-            let obj = self.tree.hit_workspace(workspace, &ray, 0.0, std::f64::MAX);
+            let obj = self.tree.hit_workspace(workspace, &ray, 0.0, f64::MAX);
             black_box(obj.is_some());
         }
     }
@@ -89,6 +89,30 @@ pub fn criterion_benchmark(c: &mut Criterion) {
 }
 pub fn bench_bvh(c: &mut Criterion) {
     let scales = (0..4).map(|p| (2u64).pow(p)).collect::<Vec<_>>();
+    let strategies = [
+        (
+            "sah",
+            BuildOptions {
+                strategy: SplitStrategy::Sah,
+                ..BuildOptions::default()
+            },
+        ),
+        (
+            "sah_bvh4",
+            BuildOptions {
+                strategy: SplitStrategy::Sah,
+                wide: true,
+                ..BuildOptions::default()
+            },
+        ),
+        (
+            "split_best",
+            BuildOptions {
+                strategy: SplitStrategy::Best,
+                ..BuildOptions::default()
+            },
+        ),
+    ];
 
     {
         let mut group = c.benchmark_group("bvh constructor");
@@ -98,9 +122,15 @@ pub fn bench_bvh(c: &mut Criterion) {
             let spheres = gen_spheres(&mut rng, *scale);
 
             group.throughput(criterion::Throughput::Elements(spheres.len() as u64));
-            group.bench_with_input(BenchmarkId::new("new", spheres.len()), &spheres, |b, i| {
-                b.iter_batched(|| i.clone(), BboxTree::new, BatchSize::PerIteration)
-            });
+            for (name, options) in &strategies {
+                group.bench_with_input(BenchmarkId::new(*name, spheres.len()), &spheres, |b, i| {
+                    b.iter_batched(
+                        || i.clone(),
+                        |items| BboxTree::with_options(items, options),
+                        BatchSize::PerIteration,
+                    )
+                });
+            }
         }
     }
 
@@ -108,23 +138,25 @@ pub fn bench_bvh(c: &mut Criterion) {
         let mut group = c.benchmark_group("bvh hit");
 
         for scale in &scales {
-            let mut rng = ChaCha20Rng::seed_from_u64(0xDEADBEEF);
-            let spheres = gen_spheres(&mut rng, *scale);
-            let builder = bvh_builder::BvhTester {
-                side_len: *scale as f64,
-                tree: BboxTree::new(spheres),
-            };
-
-            group.throughput(criterion::Throughput::Elements(builder.tree.len() as u64));
-            group.bench_with_input(
-                BenchmarkId::new("hit", builder.tree.len()),
-                &builder,
-                |b, i| {
-                    let mut rng = ChaCha20Rng::seed_from_u64(0xDEADBEEF);
-                    let mut workspace = BboxTreeWorkspace::default();
-                    b.iter(|| i.run_once(&mut rng, &mut workspace))
-                },
-            );
+            for (name, options) in &strategies {
+                let mut rng = ChaCha20Rng::seed_from_u64(0xDEADBEEF);
+                let spheres = gen_spheres(&mut rng, *scale);
+                let builder = bvh_builder::BvhTester {
+                    side_len: *scale as f64,
+                    tree: BboxTree::with_options(spheres, options),
+                };
+
+                group.throughput(criterion::Throughput::Elements(builder.tree.len() as u64));
+                group.bench_with_input(
+                    BenchmarkId::new(*name, builder.tree.len()),
+                    &builder,
+                    |b, i| {
+                        let mut rng = ChaCha20Rng::seed_from_u64(0xDEADBEEF);
+                        let mut workspace = BboxTreeWorkspace::default();
+                        b.iter(|| i.run_once(&mut rng, &mut workspace))
+                    },
+                );
+            }
         }
     }
 }
@@ -139,7 +171,7 @@ pub fn bench_aabb_hit(c: &mut Criterion) {
     let ray_miss = Ray::new(Point(Vec3::new(0.0, 0.0, 0.0)), Vec3::new(0.0, 0.0, -1.0));
 
     let start = black_box(0.0);
-    let end = black_box(std::f64::MAX);
+    let end = black_box(f64::MAX);
 
     for ray in &[("ray hit", ray_hit), ("ray miss", ray_miss)] {
         group.bench_with_input(BenchmarkId::new("fn hit", ray.0), &ray.1, |b, i| {
@@ -1,18 +1,34 @@
 use clap::Parser;
+use raytracer::{
+    bvh::bbox_tree::WireframeFormat, image::OutputFormat, render::HeatmapMetric,
+    tonemap::ToneMapOperator,
+};
 
 const DEFAULT_WIDTH: &str = "640";
 const DEFAULT_SAMPLES: &str = "100";
 const DEFAULT_REFLECT_DEPTH: &str = "50";
 const DEFAULT_OUTPUT: &str = "out.png";
+const DEFAULT_EXPOSURE: &str = "0.0";
+const DEFAULT_TONEMAP: &str = "clamp";
+const DEFAULT_WHITE_POINT: &str = "4.0";
 
 const DEFAULT_CAMERA_VFOV: &str = "20.0";
 const DEFAULT_CAMERA_FOCAL_LENGTH: &str = "1.0";
 const DEFAULT_CAMERA_APERTURE: &str = "0.001";
+const DEFAULT_CAMERA_SHUTTER: &str = "0.0";
 
 pub fn get_args() -> CliOpts {
     CliOpts::parse()
 }
 
+fn parse_white_point(s: &str) -> anyhow::Result<f64> {
+    let white_point: f64 = s.parse()?;
+    if !(white_point > 0.0 && white_point.is_finite()) {
+        anyhow::bail!("white point must be a positive number, got {}", s);
+    }
+    Ok(white_point)
+}
+
 #[derive(Parser, Debug)]
 #[clap(version = clap::crate_version!(), author = "Scott S. <scottschroeder@sent.com>")]
 pub struct CliOpts {
@@ -28,6 +44,29 @@ pub enum SubCommand {
     /// Render an image
     #[clap(subcommand)]
     Render(Render),
+    /// Report on how a scene is put together
+    #[clap(subcommand)]
+    Inspect(Inspect),
+}
+
+#[derive(Parser, Debug)]
+pub enum Inspect {
+    /// Print statistics of a saved scene's BVH, and optionally export it
+    Bvh(InspectBvh),
+}
+
+#[derive(Parser, Debug)]
+pub struct InspectBvh {
+    /// Write the node boxes of the object tree to this file
+    #[clap(short, long)]
+    pub output: Option<String>,
+
+    /// Export format (obj or json), guessed from the output extension if unset
+    #[clap(long)]
+    pub format: Option<WireframeFormat>,
+
+    /// Input file for scene_data
+    pub scene_input: String,
 }
 
 #[derive(Parser, Debug)]
@@ -109,6 +148,22 @@ pub struct RenderSettings {
     #[clap(short, long, default_value=DEFAULT_OUTPUT)]
     pub output: String,
 
+    /// Image format (png, exr or hdr), guessed from the output extension if unset
+    #[clap(long)]
+    pub format: Option<OutputFormat>,
+
+    /// Exposure compensation in stops, applied before tone mapping
+    #[clap(long, default_value=DEFAULT_EXPOSURE, allow_hyphen_values = true)]
+    pub exposure: f64,
+
+    /// Tone map operator: clamp, reinhard, extended-reinhard or aces
+    #[clap(long, default_value=DEFAULT_TONEMAP)]
+    pub tonemap: ToneMapOperator,
+
+    /// Luminance mapped to white by extended-reinhard, must be positive
+    #[clap(long, default_value=DEFAULT_WHITE_POINT, parse(try_from_str = parse_white_point))]
+    pub white_point: f64,
+
     /// Number of iterations to sample each pixel
     #[clap(short, long, default_value=DEFAULT_SAMPLES)]
     pub samples: usize,
@@ -120,6 +175,11 @@ pub struct RenderSettings {
     /// Render on a single core
     #[clap(long)]
     pub single_threaded: bool,
+
+    /// Write how much BVH work each pixel took instead of radiance, counting
+    /// nodes or primitives. A png gets false colour, exr and hdr the counts
+    #[clap(long)]
+    pub heatmap: Option<HeatmapMetric>,
 }
 
 #[derive(Parser, Debug)]
@@ -143,6 +203,14 @@ pub struct CameraSettings {
     /// Camera AspectRatio
     #[clap(long, value_enum, default_value_t=CameraAspectRatio::Std3x2)]
     pub camera_aspect_ratio: CameraAspectRatio,
+
+    /// Time the camera shutter opens, for motion blur
+    #[clap(long, default_value=DEFAULT_CAMERA_SHUTTER)]
+    pub shutter_open: f64,
+
+    /// Time the camera shutter closes, for motion blur
+    #[clap(long, default_value=DEFAULT_CAMERA_SHUTTER)]
+    pub shutter_close: f64,
 }
 
 #[derive(Debug, clap::ValueEnum, Clone)]
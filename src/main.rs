@@ -1,11 +1,12 @@
 mod argparse;
 use anyhow::Result;
 use raytracer::{
-    bvh::bbox_tree::BboxTreeWorkspace,
+    bvh::bbox_tree::{BboxTreeWorkspace, WireframeFormat},
     camera::{Camera, CameraPosition},
     image,
     render::{render_scanline, Frame},
-    scene::Scene,
+    scene::{Scene, SceneBuilder},
+    tonemap::ToneMap,
 };
 mod scenes;
 
@@ -49,6 +50,9 @@ fn main() -> Result<()> {
             argparse::Render::Cornell(args) => scenes::render_cornell_box(args),
             argparse::Render::Saved(args) => scenes::render_saved(args),
         },
+        argparse::SubCommand::Inspect(sub) => match sub {
+            argparse::Inspect::Bvh(args) => inspect_bvh(args),
+        },
         argparse::SubCommand::Test(sub) => run_test(sub),
     }
     .map_err(|e| {
@@ -62,6 +66,26 @@ fn run_test(_args: &argparse::Test) -> Result<()> {
     Ok(())
 }
 
+fn inspect_bvh(args: &argparse::InspectBvh) -> Result<()> {
+    let scene = SceneBuilder::load(args.scene_input.as_str())?;
+    let scene = scene.finalize()?;
+
+    println!("objects");
+    print!("{}", scene.object_tree().stats());
+    println!("instances");
+    print!("{}", scene.instance_tree().stats());
+
+    if let Some(output) = args.output.as_ref() {
+        let format = args
+            .format
+            .or_else(|| WireframeFormat::from_path(output))
+            .unwrap_or(WireframeFormat::Obj);
+        let f = std::io::BufWriter::new(std::fs::File::create(output)?);
+        scene.object_tree().write_wireframe(f, format)?;
+    }
+    Ok(())
+}
+
 fn render_scene(
     args: &argparse::RenderSettings,
     scene: &Scene,
@@ -85,7 +109,12 @@ fn render_scene(
     let mut image = image::Image::from_dimm(camera.dimm);
     image.samples = samples;
 
-    let frame = Frame { camera, pos, scene };
+    let frame = Frame {
+        camera,
+        pos,
+        scene,
+        heatmap: args.heatmap,
+    };
 
     log::trace!("render");
 
@@ -94,10 +123,14 @@ fn render_scene(
 
     let count = std::sync::atomic::AtomicUsize::new(0);
     let total = scanlines.len();
+    let new_workspace = || match args.heatmap {
+        Some(_) => BboxTreeWorkspace::with_counters(),
+        None => BboxTreeWorkspace::default(),
+    };
     if args.single_threaded {
         let mut rng = rand::thread_rng();
         // let mut rng = rand::prng::chacha::ChaChaRng;
-        let mut hit_stack = BboxTreeWorkspace::default();
+        let mut hit_stack = new_workspace();
         scanlines
             .iter_mut()
             .enumerate()
@@ -116,7 +149,7 @@ fn render_scene(
             })
     } else {
         scanlines.into_par_iter().enumerate().for_each_init(
-            || (rand::thread_rng(), BboxTreeWorkspace::default()),
+            || (rand::thread_rng(), new_workspace()),
             |(rng, hit_stack), (line_idx, buf)| {
                 render_scanline(&frame, rng, samples, max_depth, hit_stack, line_idx, buf);
                 let x = count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
@@ -125,6 +158,20 @@ fn render_scene(
         );
     }
 
-    image::to_image(&image, output);
-    Ok(())
+    if args.heatmap.is_some() {
+        let format = args
+            .format
+            .or_else(|| image::OutputFormat::from_path(output))
+            .unwrap_or(image::OutputFormat::Png);
+        if format == image::OutputFormat::Png {
+            return image::write_heatmap(&image, output);
+        }
+    }
+
+    let tonemap = ToneMap {
+        exposure: args.exposure,
+        operator: args.tonemap,
+        white_point: args.white_point,
+    };
+    image::write_image(&image, output, args.format, &tonemap)
 }
@@ -78,12 +78,43 @@ impl Aabb {
         true
     }
 
+    /// The part of `[t_min, t_max]` the ray spends inside the box.
+    pub fn clip(&self, r: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for a in EACH_DIMM.iter().cloned() {
+            let inv_d = 1.0 / r.direction[a];
+            let mut t0 = (self.min.0[a] - r.orig.0[a]) * inv_d;
+            let mut t1 = (self.max.0[a] - r.orig.0[a]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1)
+            }
+            t_min = fmax(t0, t_min);
+            t_max = fmin(t1, t_max);
+            if t_max < t_min {
+                return None;
+            }
+        }
+        Some((t_min, t_max))
+    }
+
     pub fn area(&self) -> f64 {
         let x = self.max.0.x() - self.min.0.x();
         let y = self.max.0.y() - self.min.0.y();
         let z = self.max.0.z() - self.min.0.z();
         x * y * z
     }
+
+    pub fn surface_area(&self) -> f64 {
+        let x = self.max.0.x() - self.min.0.x();
+        let y = self.max.0.y() - self.min.0.y();
+        let z = self.max.0.z() - self.min.0.z();
+        2.0 * (x * y + y * z + z * x)
+    }
+
+    pub fn centroid(&self) -> Point {
+        Point((self.min.0 + self.max.0).scale(0.5))
+    }
 }
 
 #[cfg(test)]
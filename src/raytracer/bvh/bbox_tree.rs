@@ -1,48 +1,445 @@
-use super::aabb::Aabb;
+use super::aabb::{surrounding_box, Aabb};
 use crate::{
-    bvh::bbox_tree::constructor::construct_tree,
-    core::Ray,
-    geometry::hittable::{Geometry, HitRecord},
+    core::{Point, Ray, Vec3},
+    geometry::hittable::{Geometry, HitRecord, Hittable},
 };
 
 mod constructor;
+mod sah;
+mod stats;
+mod wide;
 
-#[derive(Clone)]
+pub use stats::{NodeBox, TreeStats, WireframeFormat};
+
+/// Most items a leaf can point to.
+const MAX_LEAF_COUNT: usize = u16::MAX as usize;
+
+/// Cost of visiting a branch, relative to intersecting one leaf item.
+const TRAVERSAL_COST: f64 = 0.125;
+
+/// The smallest f32 above `x`, like `f32::next_up` on newer std.
+fn next_up(x: f32) -> f32 {
+    if x.is_nan() || x == f32::INFINITY {
+        return x;
+    }
+    if x == 0.0 {
+        // either zero steps up to the smallest subnormal
+        return f32::from_bits(1);
+    }
+    let bits = x.to_bits();
+    f32::from_bits(if x > 0.0 { bits + 1 } else { bits - 1 })
+}
+
+/// The largest f32 below `x`, like `f32::next_down` on newer std.
+fn next_down(x: f32) -> f32 {
+    -next_up(-x)
+}
+
+#[derive(Debug, Clone, PartialEq)]
 enum NodePointer {
-    Branch { lhs: usize, rhs: usize },
-    Leaf(usize),
+    Branch {
+        lhs: usize,
+        rhs: usize,
+    },
+    /// A range of `count` items in `leaves`
+    Leaf {
+        first: usize,
+        count: usize,
+    },
 }
 
-#[derive(Clone)]
-pub struct TreeNode {
+/// A node as the builders make it, before it is packed into a `TreeNode`.
+#[derive(Debug, Clone, PartialEq)]
+struct BuildNode {
     bbox: Aabb,
     ptr: NodePointer,
 }
 
+/// A packed node, the tree is stored depth first so the left child of a
+/// branch always directly follows it.
+#[derive(Debug, Clone, PartialEq)]
+#[repr(C)]
+pub struct TreeNode {
+    min: [f32; 3],
+    max: [f32; 3],
+    /// The right child of a branch, or the first item of a leaf
+    offset: u32,
+    /// Number of items in a leaf, zero for a branch
+    count: u16,
+    /// Axis the children are split on, the left one is nearer its minimum
+    axis: u8,
+    _pad: u8,
+}
+
+impl TreeNode {
+    fn new(bbox: &Aabb) -> TreeNode {
+        let mut node = TreeNode {
+            min: [0.0; 3],
+            max: [0.0; 3],
+            offset: 0,
+            count: 0,
+            axis: 0,
+            _pad: 0,
+        };
+        node.set_bounds(bbox);
+        node
+    }
+
+    fn set_bounds(&mut self, bbox: &Aabb) {
+        // round outwards so the packed box still contains everything
+        let round = |v: &Vec3, f: fn(f32) -> f32| {
+            [0, 1, 2].map(|d| {
+                let x = v[d] as f32;
+                if x as f64 == v[d] {
+                    x
+                } else {
+                    f(x)
+                }
+            })
+        };
+        self.min = round(&bbox.min.0, next_down);
+        self.max = round(&bbox.max.0, next_up);
+    }
+
+    fn surface_area(&self) -> f64 {
+        let [x, y, z] = [0, 1, 2].map(|d| self.max[d] as f64 - self.min[d] as f64);
+        2.0 * (x * y + y * z + z * x)
+    }
+
+    fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+
+    fn leaves(&self) -> std::ops::Range<usize> {
+        let first = self.offset as usize;
+        first..first + self.count as usize
+    }
+
+    /// Slab test against a ray with precomputed inverse direction.
+    #[inline]
+    fn hit(&self, orig: &Point, inv_dir: &[f64; 3], t_min: f64, t_max: f64) -> bool {
+        let mut t_min = t_min;
+        let mut t_max = t_max;
+        for (a, inv_d) in inv_dir.iter().enumerate() {
+            let mut t0 = (self.min[a] as f64 - orig.0[a]) * inv_d;
+            let mut t1 = (self.max[a] as f64 - orig.0[a]) * inv_d;
+            if *inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1)
+            }
+            t_min = if t0 > t_min { t0 } else { t_min };
+            t_max = if t1 < t_max { t1 } else { t_max };
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// The child to visit first and the one to visit after it.
+    fn children(&self, idx: usize, dir_is_neg: &[bool; 3]) -> (usize, usize) {
+        let (lhs, rhs) = (idx + 1, self.offset as usize);
+        if dir_is_neg[self.axis as usize] {
+            (rhs, lhs)
+        } else {
+            (lhs, rhs)
+        }
+    }
+}
+
+/// Per ray values shared by every node test.
+struct RayInfo {
+    inv_dir: [f64; 3],
+    dir_is_neg: [bool; 3],
+}
+
+impl RayInfo {
+    fn new(ray: &Ray) -> RayInfo {
+        let inv_dir = [0, 1, 2].map(|d| 1.0 / ray.direction[d]);
+        RayInfo {
+            inv_dir,
+            dir_is_neg: inv_dir.map(|x| x < 0.0),
+        }
+    }
+}
+
 pub struct BboxTree<T> {
-    root: Option<usize>,
+    /// Exact bounds of the whole tree, the nodes only keep rounded ones
+    bounds: Option<Aabb>,
     tree: Vec<TreeNode>,
+    /// `sah_cost` when the tree was built, to tell how far refitting has
+    /// let it degrade
+    build_cost: f64,
+    /// Four wide copy of `tree`, traversed instead of it when not empty
+    wide: Vec<wide::WideNode>,
     leaves: Vec<T>,
 }
 
 impl<T> Default for BboxTree<T> {
     fn default() -> Self {
         Self {
-            root: None,
+            bounds: None,
             tree: Default::default(),
+            build_cost: 0.0,
+            wide: Default::default(),
             leaves: Default::default(),
         }
     }
 }
 
+impl<T> BboxTree<T> {
+    /// Pack the nodes from a builder, depth first from `root`.
+    fn from_build(nodes: &[BuildNode], root: Option<usize>, leaves: Vec<T>) -> BboxTree<T> {
+        let mut tree = Vec::with_capacity(nodes.len());
+        if let Some(root) = root {
+            flatten(nodes, root, &mut tree);
+        }
+        let mut tree = BboxTree {
+            bounds: root.map(|idx| nodes[idx].bbox.clone()),
+            tree,
+            build_cost: 0.0,
+            wide: vec![],
+            leaves,
+        };
+        tree.build_cost = tree.sah_cost();
+        tree
+    }
+
+    /// Expected cost of a ray through the tree, in leaf item intersections.
+    pub fn sah_cost(&self) -> f64 {
+        let root_area = match self.tree.first() {
+            Some(root) => root.surface_area(),
+            None => return 0.0,
+        };
+        if root_area <= 0.0 {
+            return self.leaves.len() as f64;
+        }
+        self.tree
+            .iter()
+            .map(|node| {
+                let cost = if node.is_leaf() {
+                    node.count as f64
+                } else {
+                    TRAVERSAL_COST
+                };
+                cost * node.surface_area() / root_area
+            })
+            .sum()
+    }
+
+    /// The items, in leaf order.
+    pub fn items(&self) -> &[T] {
+        &self.leaves
+    }
+
+    /// The items, in leaf order. Call `refit` after moving any of them.
+    pub fn items_mut(&mut self) -> &mut [T] {
+        &mut self.leaves
+    }
+}
+
+fn flatten(nodes: &[BuildNode], idx: usize, tree: &mut Vec<TreeNode>) -> usize {
+    let node = &nodes[idx];
+    let out_idx = tree.len();
+    tree.push(TreeNode::new(&node.bbox));
+    match node.ptr {
+        NodePointer::Leaf { first, count } => {
+            assert!(
+                count <= MAX_LEAF_COUNT,
+                "leaf of {} items is too big",
+                count
+            );
+            tree[out_idx].offset = first as u32;
+            tree[out_idx].count = count as u16;
+        }
+        NodePointer::Branch { lhs, rhs } => {
+            // split on the axis the children are furthest apart, with the
+            // left one nearer the minimum
+            let lhs_center = nodes[lhs].bbox.centroid().0;
+            let rhs_center = nodes[rhs].bbox.centroid().0;
+            let gap = rhs_center - lhs_center;
+            let axis = (0..3)
+                .max_by(|a, b| gap[*a].abs().total_cmp(&gap[*b].abs()))
+                .unwrap();
+            let (lhs, rhs) = if gap[axis] < 0.0 {
+                (rhs, lhs)
+            } else {
+                (lhs, rhs)
+            };
+            flatten(nodes, lhs, tree);
+            let right = flatten(nodes, rhs, tree);
+            tree[out_idx].offset = right as u32;
+            tree[out_idx].axis = axis as u8;
+        }
+    }
+    out_idx
+}
+
+/// How to choose where to split the items between two branches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitStrategy {
+    /// Binned surface area heuristic
+    Sah,
+    /// The smallest of median and midpoint splits on each axis, one item
+    /// per leaf. Kept to compare against.
+    Best,
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    pub strategy: SplitStrategy,
+    /// Stop splitting once a node has this few items, if splitting wouldn't
+    /// make it cheaper anyway
+    pub max_leaf_size: usize,
+    /// Nodes this deep become leaves no matter how many items they hold
+    pub max_depth: usize,
+    /// Number of candidate split planes per axis is one less than this.
+    /// Anything below 2 is built with 2
+    pub bins: usize,
+    /// Subtrees with at least this many items are built on the rayon pool
+    pub parallel_threshold: usize,
+    /// Collapse the tree so each node has four children, tested together
+    pub wide: bool,
+    /// How many times worse than when it was built the SAH cost can get
+    /// before `refit_or_rebuild` builds again
+    pub rebuild_threshold: f64,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self {
+            strategy: SplitStrategy::Sah,
+            max_leaf_size: 4,
+            max_depth: 64,
+            bins: 16,
+            parallel_threshold: 4096,
+            wide: false,
+            rebuild_threshold: 1.5,
+        }
+    }
+}
+
+/// Work done by the traversals through one workspace.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct TraversalCounters {
+    /// Nodes whose box was tested
+    pub nodes: u64,
+    /// Leaf items intersected
+    pub primitives: u64,
+}
+
 #[derive(Default)]
 pub struct BboxTreeWorkspace {
     stack: Vec<usize>,
+    counters: Option<TraversalCounters>,
+}
+
+impl BboxTreeWorkspace {
+    /// A workspace that counts the work done by every traversal through it.
+    pub fn with_counters() -> BboxTreeWorkspace {
+        BboxTreeWorkspace {
+            stack: vec![],
+            counters: Some(TraversalCounters::default()),
+        }
+    }
+
+    /// The totals since the last reset, if counting.
+    pub fn counters(&self) -> Option<TraversalCounters> {
+        self.counters
+    }
+
+    pub fn reset_counters(&mut self) {
+        if let Some(counters) = &mut self.counters {
+            *counters = TraversalCounters::default();
+        }
+    }
+
+    /// For passing the counters on to traversals outside of the workspace.
+    pub(crate) fn counters_mut(&mut self) -> &mut Option<TraversalCounters> {
+        &mut self.counters
+    }
+}
+
+#[inline]
+pub(crate) fn count_node(counters: &mut Option<TraversalCounters>) {
+    if let Some(counters) = counters {
+        counters.nodes += 1;
+    }
+}
+
+#[inline]
+pub(crate) fn count_primitive(counters: &mut Option<TraversalCounters>) {
+    if let Some(counters) = counters {
+        counters.primitives += 1;
+    }
 }
 
 impl<T: Geometry> BboxTree<T> {
     pub fn new(items: Vec<T>) -> BboxTree<T> {
-        construct_tree(items)
+        BboxTree::with_options(items, &BuildOptions::default())
+    }
+
+    /// Recompute every node's bounds from the items, keeping the topology.
+    ///
+    /// Much cheaper than building again, but the tree gets worse as items
+    /// drift away from where they were built. Items must stay bounded.
+    pub fn refit(&mut self) {
+        let mut exact: Vec<Option<Aabb>> = vec![None; self.tree.len()];
+        // children always come after their parent
+        for idx in (0..self.tree.len()).rev() {
+            let node = &self.tree[idx];
+            let bbox = if node.is_leaf() {
+                self.leaves[node.leaves()]
+                    .iter()
+                    .filter_map(|item| item.bounding_box())
+                    .reduce(|acc, bbox| surrounding_box(&acc, &bbox))
+            } else {
+                match (&exact[idx + 1], &exact[node.offset as usize]) {
+                    (Some(lhs), Some(rhs)) => Some(surrounding_box(lhs, rhs)),
+                    (lhs, rhs) => lhs.clone().or_else(|| rhs.clone()),
+                }
+            };
+            if let Some(bbox) = &bbox {
+                self.tree[idx].set_bounds(bbox);
+            }
+            exact[idx] = bbox;
+        }
+        if !self.tree.is_empty() {
+            self.bounds = exact.swap_remove(0);
+        }
+        if self.is_wide() {
+            self.widen();
+        }
+    }
+
+    /// Refit, then build from scratch if that left the SAH cost more than
+    /// `options.rebuild_threshold` times what it was after the last build.
+    /// Returns whether it rebuilt.
+    pub fn refit_or_rebuild(&mut self, options: &BuildOptions) -> bool {
+        self.refit();
+        if self.sah_cost() <= self.build_cost * options.rebuild_threshold {
+            return false;
+        }
+        let items = std::mem::take(&mut self.leaves);
+        *self = BboxTree::with_options(items, options);
+        true
+    }
+
+    pub fn with_options(items: Vec<T>, options: &BuildOptions) -> BboxTree<T> {
+        let mut tree = match options.strategy {
+            SplitStrategy::Sah if options.bins < 2 => {
+                let options = BuildOptions {
+                    bins: 2,
+                    ..options.clone()
+                };
+                sah::construct_tree(items, &options)
+            }
+            SplitStrategy::Sah => sah::construct_tree(items, options),
+            SplitStrategy::Best => constructor::construct_tree(items),
+        };
+        if options.wide {
+            tree.widen();
+        }
+        tree
     }
 
     pub fn len(&self) -> usize {
@@ -50,7 +447,78 @@ impl<T: Geometry> BboxTree<T> {
     }
 
     pub fn is_empty(&self) -> bool {
-        self.root.is_none()
+        self.tree.is_empty()
+    }
+
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds.clone()
+    }
+
+    /// Collapse into four wide nodes, which `hit_workspace` then traverses.
+    pub fn widen(&mut self) {
+        self.wide = wide::collapse(&self.tree);
+    }
+
+    pub fn is_wide(&self) -> bool {
+        !self.wide.is_empty()
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn hit_node<'s, R, F>(
+        &'s self,
+        node_idx: usize,
+        ray: &Ray,
+        info: &RayInfo,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+        leaf_hit: &mut F,
+    ) -> Option<(R, HitRecord)>
+    where
+        F: FnMut(&'s T, &Ray, f64, f64, &mut Option<TraversalCounters>) -> Option<(R, HitRecord)>,
+    {
+        let node = &self.tree[node_idx];
+        count_node(counters);
+        if !node.hit(&ray.orig, &info.inv_dir, t_min, t_max) {
+            return None;
+        }
+        if node.is_leaf() {
+            let mut closest: Option<(R, HitRecord)> = None;
+            for leaf in &self.leaves[node.leaves()] {
+                count_primitive(counters);
+                let t_closest = closest.as_ref().map(|(_, r)| r.t).unwrap_or(t_max);
+                if let Some(hit) = leaf_hit(leaf, ray, t_min, t_closest, counters) {
+                    closest = Some(hit)
+                }
+            }
+            return closest;
+        }
+        let (near, far) = node.children(node_idx, &info.dir_is_neg);
+        let closest = self.hit_node(near, ray, info, t_min, t_max, counters, leaf_hit);
+        let t_closest = closest.as_ref().map(|(_, r)| r.t).unwrap_or(t_max);
+        self.hit_node(far, ray, info, t_min, t_closest, counters, leaf_hit)
+            .or(closest)
+    }
+
+    /// Recursive traversal, where the leaf intersection is provided by the
+    /// caller. Used for trees nested inside another traversal, the work done
+    /// is added to the outer traversal's `counters`.
+    pub fn hit_with<'s, R, F>(
+        &'s self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+        mut leaf_hit: F,
+    ) -> Option<(R, HitRecord)>
+    where
+        F: FnMut(&'s T, &Ray, f64, f64, &mut Option<TraversalCounters>) -> Option<(R, HitRecord)>,
+    {
+        if self.is_empty() {
+            return None;
+        }
+        let info = RayInfo::new(ray);
+        self.hit_node(0, ray, &info, t_min, t_max, counters, &mut leaf_hit)
     }
 
     pub fn hit_workspace(
@@ -60,35 +528,184 @@ impl<T: Geometry> BboxTree<T> {
         t_min: f64,
         t_max: f64,
     ) -> Option<(&T, HitRecord)> {
-        let root_idx = self.root?;
+        self.hit_workspace_with(
+            workspace,
+            ray,
+            t_min,
+            t_max,
+            |obj, ray, t_min, t_max, counters| {
+                obj.hit_counted(ray, t_min, t_max, counters)
+                    .map(|hit| (obj, hit))
+            },
+        )
+    }
+
+    /// Same as `hit_workspace`, but the leaf intersection is provided by the
+    /// caller, so it can report something other than the leaf itself.
+    pub fn hit_workspace_with<'s, R, F>(
+        &'s self,
+        workspace: &mut BboxTreeWorkspace,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        mut leaf_hit: F,
+    ) -> Option<(R, HitRecord)>
+    where
+        F: FnMut(&'s T, &Ray, f64, f64, &mut Option<TraversalCounters>) -> Option<(R, HitRecord)>,
+    {
+        if self.is_empty() {
+            return None;
+        }
+        if self.is_wide() {
+            return self.hit_wide(workspace, ray, t_min, t_max, leaf_hit);
+        }
+        let info = RayInfo::new(ray);
 
         workspace.stack.truncate(0);
-        workspace.stack.push(root_idx);
+        workspace.stack.push(0);
 
-        let mut closest: Option<(&T, HitRecord)> = None;
+        let mut closest: Option<(R, HitRecord)> = None;
 
         while let Some(node_idx) = workspace.stack.pop() {
             let t_closest = closest.as_ref().map(|(_, r)| r.t).unwrap_or(t_max);
 
             let node = &self.tree[node_idx];
-            if !node.bbox.hit2(ray, t_min, t_closest) {
+            count_node(&mut workspace.counters);
+            if !node.hit(&ray.orig, &info.inv_dir, t_min, t_closest) {
                 continue;
             }
-            match node.ptr {
-                NodePointer::Branch { lhs, rhs } => {
-                    workspace.stack.push(lhs);
-                    workspace.stack.push(rhs);
-                }
-                NodePointer::Leaf(idx) => {
-                    let obj = &self.leaves[idx];
-                    if let Some(hit) = obj.hit(ray, t_min, t_closest) {
-                        closest = Some((obj, hit))
+            if node.is_leaf() {
+                for leaf in &self.leaves[node.leaves()] {
+                    count_primitive(&mut workspace.counters);
+                    let t_closest = closest.as_ref().map(|(_, r)| r.t).unwrap_or(t_max);
+                    if let Some(hit) =
+                        leaf_hit(leaf, ray, t_min, t_closest, &mut workspace.counters)
+                    {
+                        closest = Some(hit)
                     }
                 }
+            } else {
+                // the near child goes on top, so it is visited first and can
+                // cut the far one short
+                let (near, far) = node.children(node_idx, &info.dir_is_neg);
+                workspace.stack.push(far);
+                workspace.stack.push(near);
             }
         }
         closest
     }
+
+    #[allow(clippy::too_many_arguments)]
+    fn occluded_node<'s, F>(
+        &'s self,
+        node_idx: usize,
+        ray: &Ray,
+        info: &RayInfo,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+        leaf_occluded: &mut F,
+    ) -> bool
+    where
+        F: FnMut(&'s T, &Ray, f64, f64, &mut Option<TraversalCounters>) -> bool,
+    {
+        let node = &self.tree[node_idx];
+        count_node(counters);
+        if !node.hit(&ray.orig, &info.inv_dir, t_min, t_max) {
+            return false;
+        }
+        if node.is_leaf() {
+            return self.leaves[node.leaves()].iter().any(|leaf| {
+                count_primitive(counters);
+                leaf_occluded(leaf, ray, t_min, t_max, counters)
+            });
+        }
+        let (near, far) = node.children(node_idx, &info.dir_is_neg);
+        self.occluded_node(near, ray, info, t_min, t_max, counters, leaf_occluded)
+            || self.occluded_node(far, ray, info, t_min, t_max, counters, leaf_occluded)
+    }
+
+    /// Recursive any hit query, like `hit_with` but it stops at the first
+    /// leaf that reports a hit.
+    pub fn occluded_with<'s, F>(
+        &'s self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+        mut leaf_occluded: F,
+    ) -> bool
+    where
+        F: FnMut(&'s T, &Ray, f64, f64, &mut Option<TraversalCounters>) -> bool,
+    {
+        if self.is_empty() {
+            return false;
+        }
+        let info = RayInfo::new(ray);
+        self.occluded_node(0, ray, &info, t_min, t_max, counters, &mut leaf_occluded)
+    }
+
+    /// Whether any item is hit in `[t_min, t_max]`, for shadow rays.
+    pub fn occluded_workspace(
+        &self,
+        workspace: &mut BboxTreeWorkspace,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let leaf_occluded = |obj: &T, ray: &Ray, t_min, t_max, counters: &mut _| {
+            obj.occluded_counted(ray, t_min, t_max, counters)
+        };
+        if self.is_wide() {
+            return self.occluded_wide(workspace, ray, t_min, t_max, leaf_occluded);
+        }
+        let info = RayInfo::new(ray);
+
+        workspace.stack.truncate(0);
+        workspace.stack.push(0);
+
+        while let Some(node_idx) = workspace.stack.pop() {
+            let node = &self.tree[node_idx];
+            count_node(&mut workspace.counters);
+            if !node.hit(&ray.orig, &info.inv_dir, t_min, t_max) {
+                continue;
+            }
+            if node.is_leaf() {
+                if self.leaves[node.leaves()].iter().any(|leaf| {
+                    count_primitive(&mut workspace.counters);
+                    leaf_occluded(leaf, ray, t_min, t_max, &mut workspace.counters)
+                }) {
+                    return true;
+                }
+            } else {
+                let (near, far) = node.children(node_idx, &info.dir_is_neg);
+                workspace.stack.push(far);
+                workspace.stack.push(near);
+            }
+        }
+        false
+    }
+}
+
+/// Recursive traversal for trees nested inside another geometry, where there
+/// is no workspace to borrow.
+impl<T: Geometry> Hittable for BboxTree<T> {
+    type Leaf = T;
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(&T, HitRecord)> {
+        self.hit_with(
+            ray,
+            t_min,
+            t_max,
+            &mut None,
+            |obj, ray, t_min, t_max, counters| {
+                obj.hit_counted(ray, t_min, t_max, counters)
+                    .map(|hit| (obj, hit))
+            },
+        )
+    }
 }
 
 #[cfg(test)]
@@ -102,8 +719,44 @@ mod tests {
     #[test]
     fn size_of_tree_node() {
         // we want this small to fit in cache. if this grows, there should be a good reason.
-        // NOTE: its too big right now
-        assert_eq!(std::mem::size_of::<TreeNode>(), 72);
+        assert_eq!(std::mem::size_of::<TreeNode>(), 32);
+    }
+
+    #[test]
+    fn packed_bounds_are_conservative() {
+        let bbox = Aabb {
+            min: Point(Vec3::new(0.1, -0.3, 1e-9)),
+            max: Point(Vec3::new(0.7, 1.0 / 3.0, 123.456)),
+        };
+        let node = TreeNode::new(&bbox);
+        for d in 0..3 {
+            assert!(node.min[d] as f64 <= bbox.min.0[d]);
+            assert!(node.max[d] as f64 >= bbox.max.0[d]);
+        }
+        // exactly representable values are kept as they are
+        let exact = Aabb {
+            min: Point(Vec3::new(-0.5, 0.0, 2.0)),
+            max: Point(Vec3::new(0.5, 1.0, 4.0)),
+        };
+        let node = TreeNode::new(&exact);
+        assert_eq!(node.min, [-0.5, 0.0, 2.0]);
+        assert_eq!(node.max, [0.5, 1.0, 4.0]);
+    }
+
+    #[test]
+    fn step_to_neighbouring_floats() {
+        assert_eq!(next_up(1.0), 1.0 + f32::EPSILON);
+        assert_eq!(next_down(1.0), 1.0 - f32::EPSILON / 2.0);
+        assert_eq!(next_down(-1.0), -1.0 - f32::EPSILON);
+        assert_eq!(next_up(-1.0), -1.0 + f32::EPSILON / 2.0);
+        for zero in [0.0, -0.0] {
+            assert_eq!(next_up(zero), f32::from_bits(1));
+            assert_eq!(next_down(zero), -f32::from_bits(1));
+        }
+        assert_eq!(next_up(f32::MAX), f32::INFINITY);
+        assert_eq!(next_up(f32::INFINITY), f32::INFINITY);
+        assert_eq!(next_up(f32::NEG_INFINITY), -f32::MAX);
+        assert!(next_up(f32::NAN).is_nan());
     }
 
     #[test]
@@ -112,10 +765,7 @@ mod tests {
 
         let mut stack = BboxTreeWorkspace::default();
         let r = Ray::new(Point(Vec3::default()), Vec3::default());
-        assert_eq!(
-            empty_tree.hit_workspace(&mut stack, &r, 0., std::f64::MAX),
-            None
-        );
+        assert_eq!(empty_tree.hit_workspace(&mut stack, &r, 0., f64::MAX), None);
     }
 
     #[test]
@@ -127,7 +777,7 @@ mod tests {
 
         let mut stack = BboxTreeWorkspace::default();
         let r = Ray::new(Point(Vec3::default()), Vec3::new(1., 0., 0.));
-        assert_eq!(bbox.hit_workspace(&mut stack, &r, 0., std::f64::MAX), None);
+        assert_eq!(bbox.hit_workspace(&mut stack, &r, 0., f64::MAX), None);
     }
 
     #[test]
@@ -141,7 +791,7 @@ mod tests {
         let mut stack = BboxTreeWorkspace::default();
         let r = Ray::new(Point(Vec3::default()), Vec3::new(0., 0., -1.));
 
-        let hit_result = bbox.hit_workspace(&mut stack, &r, 0., std::f64::MAX);
+        let hit_result = bbox.hit_workspace(&mut stack, &r, 0., f64::MAX);
         assert!(hit_result.is_some());
         let (obj, _) = hit_result.unwrap();
         assert_eq!(obj, &spheres[0]);
@@ -159,14 +809,11 @@ mod tests {
 
         // Verify that we will hit the box
         assert!(
-            spheres[0]
-                .bounding_box()
-                .unwrap()
-                .hit2(&r, 0.0, std::f64::MAX),
+            spheres[0].bounding_box().unwrap().hit2(&r, 0.0, f64::MAX),
             "bad test setup, did not hit bounding box"
         );
 
-        let hit_result = bbox.hit_workspace(&mut stack, &r, 0., std::f64::MAX);
+        let hit_result = bbox.hit_workspace(&mut stack, &r, 0., f64::MAX);
         assert!(hit_result.is_none());
     }
 
@@ -188,7 +835,7 @@ mod tests {
         let mut stack = BboxTreeWorkspace::default();
         let r = Ray::new(Point(Vec3::default()), Vec3::new(0., 0., -1.));
 
-        let hit_result = bbox.hit_workspace(&mut stack, &r, 0., std::f64::MAX);
+        let hit_result = bbox.hit_workspace(&mut stack, &r, 0., f64::MAX);
         assert!(hit_result.is_some());
         let (obj, _) = hit_result.unwrap();
         assert_eq!(obj, &first);
@@ -213,14 +860,11 @@ mod tests {
 
         // Verify that we will hit the box
         assert!(
-            spheres[0]
-                .bounding_box()
-                .unwrap()
-                .hit2(&r, 0.0, std::f64::MAX),
+            spheres[0].bounding_box().unwrap().hit2(&r, 0.0, f64::MAX),
             "bad test setup, did not hit bounding box"
         );
 
-        let hit_result = bbox.hit_workspace(&mut stack, &r, 0., std::f64::MAX);
+        let hit_result = bbox.hit_workspace(&mut stack, &r, 0., f64::MAX);
         assert!(hit_result.is_some());
         let (obj, _) = hit_result.unwrap();
         assert_eq!(obj, &spheres[1]);
@@ -231,4 +875,125 @@ mod tests {
     //
     // TODO test max t is not far enough
     // TODO test min t is too far
+
+    /// `n` spheres of radius 0.5 along the x axis, 2 apart.
+    pub(super) fn row_of_spheres(n: usize) -> Vec<Sphere> {
+        (0..n)
+            .map(|i| Sphere {
+                center: Point(Vec3::new(2.0 * i as f64, 0.0, 0.0)),
+                radius: 0.5,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn refit_follows_moved_items() {
+        let mut tree = BboxTree::new(row_of_spheres(64));
+        let cost = tree.sah_cost();
+        for sphere in tree.items_mut() {
+            sphere.center.0[1] += 5.0;
+        }
+        assert!(!tree.refit_or_rebuild(&BuildOptions::default()));
+        assert!((tree.sah_cost() - cost).abs() < 1e-6);
+        assert_eq!(tree.bounding_box().unwrap().min.0.y(), 4.5);
+
+        let mut workspace = BboxTreeWorkspace::default();
+        let r = Ray::new(Point(Vec3::new(20.0, 5.0, 10.0)), Vec3::new(0.0, 0.0, -1.0));
+        let (obj, _) = tree
+            .hit_workspace(&mut workspace, &r, 0.0, f64::MAX)
+            .unwrap();
+        assert_eq!(obj.center.0.x(), 20.0);
+        let r = Ray::new(Point(Vec3::new(20.0, 0.0, 10.0)), Vec3::new(0.0, 0.0, -1.0));
+        assert!(tree
+            .hit_workspace(&mut workspace, &r, 0.0, f64::MAX)
+            .is_none());
+    }
+
+    #[test]
+    fn rebuild_when_cost_degrades() {
+        let options = BuildOptions {
+            wide: true,
+            ..BuildOptions::default()
+        };
+        let mut tree = BboxTree::with_options(row_of_spheres(64), &options);
+        // scatter the items, so every leaf spans most of the row
+        for (i, sphere) in tree.items_mut().iter_mut().enumerate() {
+            sphere.center.0[0] = 2.0 * ((i * 37) % 64) as f64;
+        }
+        tree.refit();
+        let refit_cost = tree.sah_cost();
+        assert!(refit_cost > 1.5 * tree.build_cost);
+
+        assert!(tree.refit_or_rebuild(&options));
+        assert!(tree.sah_cost() < refit_cost);
+        assert!(tree.is_wide());
+        assert_eq!(tree.len(), 64);
+    }
+
+    #[test]
+    fn occluded_agrees_with_hit() {
+        use rand::{Rng, SeedableRng};
+
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(3);
+        let spheres = (0..200)
+            .map(|_| Sphere {
+                center: Point(Vec3::random_range_with_rng(&mut rng, -10.0, 10.0)),
+                radius: rng.gen_range(0.1..1.0),
+            })
+            .collect::<Vec<_>>();
+        let binary = BboxTree::new(spheres.clone());
+        let mut wide = BboxTree::new(spheres);
+        wide.widen();
+
+        let mut workspace = BboxTreeWorkspace::default();
+        for _ in 0..1000 {
+            let orig = Point(Vec3::random_range_with_rng(&mut rng, -12.0, 12.0));
+            let dir = Vec3::random_range_with_rng(&mut rng, -1.0, 1.0);
+            let r = Ray::new(orig, dir);
+            let t_max = rng.gen_range(0.0..20.0);
+            let expected = binary
+                .hit_workspace(&mut workspace, &r, 0.001, t_max)
+                .is_some();
+            assert_eq!(
+                binary.occluded_workspace(&mut workspace, &r, 0.001, t_max),
+                expected
+            );
+            assert_eq!(
+                wide.occluded_workspace(&mut workspace, &r, 0.001, t_max),
+                expected
+            );
+            assert_eq!(
+                binary.occluded_with(&r, 0.001, t_max, &mut None, |obj, ray, t_min, t_max, _| {
+                    obj.occluded(ray, t_min, t_max)
+                }),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn counters_only_when_asked() {
+        let tree = BboxTree::new(row_of_spheres(16));
+        let r = Ray::new(Point(Vec3::new(6.0, 0.0, 10.0)), Vec3::new(0.0, 0.0, -1.0));
+
+        let mut workspace = BboxTreeWorkspace::default();
+        tree.hit_workspace(&mut workspace, &r, 0.0, f64::MAX)
+            .unwrap();
+        assert_eq!(workspace.counters(), None);
+
+        let mut workspace = BboxTreeWorkspace::with_counters();
+        tree.hit_workspace(&mut workspace, &r, 0.0, f64::MAX)
+            .unwrap();
+        let counters = workspace.counters().unwrap();
+        assert!(counters.nodes > 1 && counters.nodes < tree.tree.len() as u64);
+        assert!(counters.primitives >= 1);
+
+        // the wide tree tests fewer nodes for the same ray
+        let mut wide = BboxTree::new(row_of_spheres(16));
+        wide.widen();
+        workspace.reset_counters();
+        wide.hit_workspace(&mut workspace, &r, 0.0, f64::MAX)
+            .unwrap();
+        assert!(workspace.counters().unwrap().nodes < counters.nodes);
+    }
 }
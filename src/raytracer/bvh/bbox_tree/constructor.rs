@@ -1,6 +1,6 @@
 use std::collections::HashSet;
 
-use super::{BboxTree, NodePointer, TreeNode};
+use super::{BboxTree, BuildNode, NodePointer};
 use crate::{
     bvh::aabb::{bounding, surrounding_box},
     geometry::hittable::Geometry,
@@ -13,13 +13,20 @@ pub fn construct_tree<T: Geometry>(items: Vec<T>) -> BboxTree<T> {
     let mut leaf_nodes = Vec::with_capacity(items.len());
     for (idx, value) in items.iter().enumerate() {
         if let Some(bbox) = value.bounding_box() {
-            leaf_nodes.push(TreeNode {
+            leaf_nodes.push(BuildNode {
                 bbox,
-                ptr: NodePointer::Leaf(idx),
+                ptr: NodePointer::Leaf {
+                    first: idx,
+                    count: 1,
+                },
             })
         }
     }
 
+    if leaf_nodes.is_empty() {
+        return BboxTree::from_build(&[], None, items);
+    }
+
     let mut tree = Vec::new();
     let ordered_by_dimm = LeafDimmSlices::new(&leaf_nodes);
     let all_nodes = BoxSet {
@@ -28,11 +35,7 @@ pub fn construct_tree<T: Geometry>(items: Vec<T>) -> BboxTree<T> {
     let root_node = partition_nodes(&mut tree, &leaf_nodes, &all_nodes, &ordered_by_dimm);
     let root = Some(tree.len());
     tree.push(root_node);
-    BboxTree {
-        root,
-        tree,
-        leaves: items,
-    }
+    BboxTree::from_build(&tree, root, items)
 }
 
 #[derive(Default)]
@@ -62,7 +65,7 @@ struct LeafDimmSlices {
 }
 
 impl LeafDimmSlices {
-    fn new(nodes: &[TreeNode]) -> LeafDimmSlices {
+    fn new(nodes: &[BuildNode]) -> LeafDimmSlices {
         LeafDimmSlices {
             x_min: sorted_with_idx(nodes.iter().map(|tn| tn.bbox.min.0.x())),
             // x_max: sorted_with_idx(nodes.iter().map(|tn| tn.bbox.max.0.x())),
@@ -123,7 +126,7 @@ fn split_space(input: &BoxSet, order: &[(usize, f64)]) -> (BoxSet, BoxSet) {
     (lhs, rhs)
 }
 
-fn total_area(nodes: &[TreeNode], lhs: &BoxSet, rhs: &BoxSet) -> f64 {
+fn total_area(nodes: &[BuildNode], lhs: &BoxSet, rhs: &BoxSet) -> f64 {
     let lhs_area = bounding(lhs.inner.iter().map(|bidx| &nodes[*bidx].bbox))
         .map(|b| b.area())
         .unwrap_or(0.0);
@@ -133,7 +136,7 @@ fn total_area(nodes: &[TreeNode], lhs: &BoxSet, rhs: &BoxSet) -> f64 {
     lhs_area + rhs_area
 }
 
-fn evaluate_split(nodes: &[TreeNode], sides: &(BoxSet, BoxSet), name: &str) -> f64 {
+fn evaluate_split(nodes: &[BuildNode], sides: &(BoxSet, BoxSet), name: &str) -> f64 {
     let (lhs, rhs) = sides;
     let area = total_area(nodes, lhs, rhs);
 
@@ -148,20 +151,21 @@ fn evaluate_split(nodes: &[TreeNode], sides: &(BoxSet, BoxSet), name: &str) -> f
     area
 }
 
-fn split_best(nodes: &[TreeNode], input: &BoxSet, all_order: &LeafDimmSlices) -> (BoxSet, BoxSet) {
-    let mut splits = Vec::new();
-    splits.push(("xmin_median", split_median(input, &all_order.x_min)));
-    // splits.push(("xmax_median", split_median(input, &all_order.x_max)));
-    splits.push(("xmin_space", split_space(input, &all_order.x_min)));
-    // splits.push(("xmax_space", split_space(input, &all_order.x_max)));
-    splits.push(("ymin_median", split_median(input, &all_order.y_min)));
-    // splits.push(("ymax_median", split_median(input, &all_order.y_max)));
-    splits.push(("ymin_space", split_space(input, &all_order.y_min)));
-    // splits.push(("ymax_space", split_space(input, &all_order.y_max)));
-    splits.push(("zmin_median", split_median(input, &all_order.z_min)));
-    // splits.push(("zmax_median", split_median(input, &all_order.z_max)));
-    splits.push(("zmin_space", split_space(input, &all_order.z_min)));
-    // splits.push(("zmax_space", split_space(input, &all_order.z_max)));
+fn split_best(nodes: &[BuildNode], input: &BoxSet, all_order: &LeafDimmSlices) -> (BoxSet, BoxSet) {
+    let splits = vec![
+        ("xmin_median", split_median(input, &all_order.x_min)),
+        // ("xmax_median", split_median(input, &all_order.x_max)),
+        ("xmin_space", split_space(input, &all_order.x_min)),
+        // ("xmax_space", split_space(input, &all_order.x_max)),
+        ("ymin_median", split_median(input, &all_order.y_min)),
+        // ("ymax_median", split_median(input, &all_order.y_max)),
+        ("ymin_space", split_space(input, &all_order.y_min)),
+        // ("ymax_space", split_space(input, &all_order.y_max)),
+        ("zmin_median", split_median(input, &all_order.z_min)),
+        // ("zmax_median", split_median(input, &all_order.z_max)),
+        ("zmin_space", split_space(input, &all_order.z_min)),
+        // ("zmax_space", split_space(input, &all_order.z_max)),
+    ];
 
     let (_, sides, name) = splits
         .into_iter()
@@ -180,11 +184,11 @@ fn split_best(nodes: &[TreeNode], input: &BoxSet, all_order: &LeafDimmSlices) ->
 }
 
 fn partition_nodes(
-    tree: &mut Vec<TreeNode>,
-    nodes: &[TreeNode],
+    tree: &mut Vec<BuildNode>,
+    nodes: &[BuildNode],
     working_set: &BoxSet,
     all_order: &LeafDimmSlices,
-) -> TreeNode {
+) -> BuildNode {
     match working_set.inner.len() {
         0 => panic!("trying to partition an empty set"),
         1 => nodes[*working_set.inner.iter().next().unwrap()].clone(),
@@ -200,7 +204,7 @@ fn partition_nodes(
             let rhs_idx = tree.len();
             tree.push(rhs);
 
-            TreeNode {
+            BuildNode {
                 bbox,
                 ptr: NodePointer::Branch {
                     lhs: lhs_idx,
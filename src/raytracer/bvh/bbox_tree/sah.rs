@@ -0,0 +1,371 @@
+use super::{BboxTree, BuildNode, BuildOptions, NodePointer, MAX_LEAF_COUNT, TRAVERSAL_COST};
+use crate::{
+    bvh::aabb::{bounding, surrounding_box, Aabb},
+    core::{Point, Vec3},
+    geometry::hittable::Geometry,
+};
+
+struct Primitive {
+    bbox: Aabb,
+    centroid: Point,
+}
+
+#[derive(Clone, Copy)]
+struct Split {
+    axis: usize,
+    /// Last bin on the left hand side
+    bin: usize,
+    cost: f64,
+}
+
+struct Builder<'a> {
+    options: &'a BuildOptions,
+    primitives: &'a [Primitive],
+    tree: Vec<BuildNode>,
+}
+
+impl<'a> Builder<'a> {
+    fn bin_of(&self, idx: usize, axis: usize, min: f64, extent: f64) -> usize {
+        let c = self.primitives[idx].centroid.0[axis];
+        let bins = self.options.bins;
+        (((c - min) / extent * bins as f64) as usize).min(bins - 1)
+    }
+
+    fn centroid_bounds(&self, indices: &[usize]) -> (Vec3, Vec3) {
+        let mut min = Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY);
+        let mut max = Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY);
+        for idx in indices {
+            let c = self.primitives[*idx].centroid.0;
+            for d in 0..3 {
+                min[d] = min[d].min(c[d]);
+                max[d] = max[d].max(c[d]);
+            }
+        }
+        (min, max)
+    }
+
+    /// Evaluate the surface area heuristic between every pair of bins on
+    /// every axis, and keep the cheapest.
+    fn find_split(&self, indices: &[usize], bbox: &Aabb, min: &Vec3, max: &Vec3) -> Option<Split> {
+        let bins = self.options.bins;
+        let area = bbox.surface_area();
+        let inv_area = if area > 0.0 { 1.0 / area } else { 0.0 };
+        let mut best: Option<Split> = None;
+
+        for axis in 0..3 {
+            let extent = max[axis] - min[axis];
+            if extent <= 0.0 {
+                continue;
+            }
+            let mut counts = vec![0usize; bins];
+            let mut boxes: Vec<Option<Aabb>> = vec![None; bins];
+            for idx in indices {
+                let b = self.bin_of(*idx, axis, min[axis], extent);
+                counts[b] += 1;
+                let prim = &self.primitives[*idx].bbox;
+                boxes[b] = Some(match &boxes[b] {
+                    Some(current) => surrounding_box(current, prim),
+                    None => prim.clone(),
+                });
+            }
+
+            // sweep from the right to find the cost of everything past a bin
+            let mut right_cost = vec![0.0; bins];
+            let mut acc: Option<Aabb> = None;
+            let mut count = 0;
+            for b in (1..bins).rev() {
+                acc = merge(acc, &boxes[b]);
+                count += counts[b];
+                right_cost[b - 1] = acc.as_ref().map_or(0.0, |a| a.surface_area()) * count as f64;
+            }
+
+            let mut acc: Option<Aabb> = None;
+            let mut count = 0;
+            for b in 0..bins - 1 {
+                acc = merge(acc, &boxes[b]);
+                count += counts[b];
+                let left_cost = acc.as_ref().map_or(0.0, |a| a.surface_area()) * count as f64;
+                let cost = TRAVERSAL_COST + (left_cost + right_cost[b]) * inv_area;
+                let better = match &best {
+                    Some(s) => cost < s.cost,
+                    None => true,
+                };
+                if better {
+                    best = Some(Split { axis, bin: b, cost });
+                }
+            }
+        }
+        best
+    }
+
+    /// Where to split `indices`, partitioning them around it, or `None` if
+    /// they should stay a leaf.
+    fn split(&self, indices: &mut [usize], bbox: &Aabb, depth: usize) -> Option<usize> {
+        let fits_leaf = indices.len() <= self.options.max_leaf_size.min(MAX_LEAF_COUNT);
+        if indices.len() <= 1
+            || (depth >= self.options.max_depth && indices.len() <= MAX_LEAF_COUNT)
+        {
+            return None;
+        }
+
+        let (min, max) = self.centroid_bounds(indices);
+        let split = self.find_split(indices, bbox, &min, &max);
+        let mid = match split {
+            Some(split) if fits_leaf && split.cost >= indices.len() as f64 => return None,
+            Some(split) => {
+                let extent = max[split.axis] - min[split.axis];
+                partition(indices, |idx| {
+                    self.bin_of(idx, split.axis, min[split.axis], extent) <= split.bin
+                })
+            }
+            // every centroid is in the same place
+            None if fits_leaf => return None,
+            None => 0,
+        };
+        // when the heuristic can't separate the items, halve them
+        if mid == 0 || mid == indices.len() {
+            Some(indices.len() / 2)
+        } else {
+            Some(mid)
+        }
+    }
+
+    /// Nodes are laid out depth first, a parent before its left subtree and
+    /// then its right one.
+    fn build(&mut self, indices: &mut [usize], first: usize, depth: usize) -> usize {
+        let bbox = bounding(indices.iter().map(|idx| &self.primitives[*idx].bbox))
+            .expect("building a node without items");
+        let node_idx = self.tree.len();
+        let mid = self.split(indices, &bbox, depth);
+        self.tree.push(BuildNode {
+            bbox,
+            ptr: NodePointer::Leaf {
+                first,
+                count: indices.len(),
+            },
+        });
+        let mid = match mid {
+            Some(mid) => mid,
+            None => return node_idx,
+        };
+
+        let parallel = indices.len() >= self.options.parallel_threshold;
+        let (lhs_indices, rhs_indices) = indices.split_at_mut(mid);
+        let (lhs, rhs) = if parallel {
+            // build both sides on their own, then splice them in the same
+            // order the serial build would have
+            let (lhs_nodes, rhs_nodes) = rayon::join(
+                || self.subtree(lhs_indices, first, depth + 1),
+                || self.subtree(rhs_indices, first + mid, depth + 1),
+            );
+            (self.append(lhs_nodes), self.append(rhs_nodes))
+        } else {
+            let lhs = self.build(lhs_indices, first, depth + 1);
+            let rhs = self.build(rhs_indices, first + mid, depth + 1);
+            (lhs, rhs)
+        };
+        self.tree[node_idx].ptr = NodePointer::Branch { lhs, rhs };
+        node_idx
+    }
+
+    fn subtree(&self, indices: &mut [usize], first: usize, depth: usize) -> Vec<BuildNode> {
+        let mut builder = Builder {
+            options: self.options,
+            primitives: self.primitives,
+            tree: Vec::with_capacity(2 * indices.len()),
+        };
+        builder.build(indices, first, depth);
+        builder.tree
+    }
+
+    /// Add a subtree built on its own, returning the index of its root.
+    fn append(&mut self, nodes: Vec<BuildNode>) -> usize {
+        let offset = self.tree.len();
+        self.tree.extend(nodes.into_iter().map(|mut node| {
+            if let NodePointer::Branch { lhs, rhs } = &mut node.ptr {
+                *lhs += offset;
+                *rhs += offset;
+            }
+            node
+        }));
+        offset
+    }
+}
+
+fn merge(acc: Option<Aabb>, next: &Option<Aabb>) -> Option<Aabb> {
+    match (acc, next) {
+        (Some(a), Some(b)) => Some(surrounding_box(&a, b)),
+        (a, b) => a.or_else(|| b.clone()),
+    }
+}
+
+/// Move the items matching `pred` to the front, returning how many there
+/// are.
+fn partition<F: Fn(usize) -> bool>(indices: &mut [usize], pred: F) -> usize {
+    let mut mid = 0;
+    for k in 0..indices.len() {
+        if pred(indices[k]) {
+            indices.swap(mid, k);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+pub fn construct_tree<T: Geometry>(items: Vec<T>, options: &BuildOptions) -> BboxTree<T> {
+    let mut primitives = Vec::with_capacity(items.len());
+    let mut bounded = Vec::with_capacity(items.len());
+    let mut unbounded = Vec::new();
+    for (idx, item) in items.iter().enumerate() {
+        match item.bounding_box() {
+            Some(bbox) => {
+                primitives.push(Primitive {
+                    centroid: bbox.centroid(),
+                    bbox,
+                });
+                bounded.push(idx);
+            }
+            None => unbounded.push(idx),
+        }
+    }
+    if primitives.is_empty() {
+        return BboxTree::from_build(&[], None, items);
+    }
+
+    let mut builder = Builder {
+        options,
+        primitives: &primitives,
+        tree: Vec::with_capacity(2 * primitives.len()),
+    };
+    let mut indices = (0..primitives.len()).collect::<Vec<_>>();
+    let root = builder.build(&mut indices, 0, 0);
+    let tree = builder.tree;
+
+    // store the items in leaf order, so every leaf is a contiguous range.
+    // items without a bounding box are kept at the end, out of the tree
+    let mut slots = items.into_iter().map(Some).collect::<Vec<_>>();
+    let leaves = indices
+        .iter()
+        .map(|idx| bounded[*idx])
+        .chain(unbounded)
+        .map(|idx| slots[idx].take().unwrap())
+        .collect();
+
+    BboxTree::from_build(&tree, Some(root), leaves)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bvh::bbox_tree::{tests::row_of_spheres, BboxTreeWorkspace},
+        core::{Point, Ray},
+        geometry::sphere::Sphere,
+    };
+
+    fn leaf_sizes<T>(tree: &BboxTree<T>) -> Vec<usize> {
+        tree.tree
+            .iter()
+            .filter(|node| node.is_leaf())
+            .map(|node| node.count as usize)
+            .collect()
+    }
+
+    #[test]
+    fn respects_leaf_size() {
+        let options = BuildOptions {
+            max_leaf_size: 2,
+            ..BuildOptions::default()
+        };
+        let tree = BboxTree::with_options(row_of_spheres(64), &options);
+        let sizes = leaf_sizes(&tree);
+        assert_eq!(sizes.iter().sum::<usize>(), 64);
+        assert!(sizes.iter().all(|s| *s <= 2));
+    }
+
+    #[test]
+    fn max_depth_makes_big_leaves() {
+        let options = BuildOptions {
+            max_depth: 2,
+            ..BuildOptions::default()
+        };
+        let tree = BboxTree::with_options(row_of_spheres(64), &options);
+        let sizes = leaf_sizes(&tree);
+        assert_eq!(sizes.len(), 4);
+        assert_eq!(sizes.iter().sum::<usize>(), 64);
+    }
+
+    #[test]
+    fn too_few_bins_still_split() {
+        for bins in 0..2 {
+            let options = BuildOptions {
+                bins,
+                max_leaf_size: 2,
+                ..BuildOptions::default()
+            };
+            let tree = BboxTree::with_options(row_of_spheres(64), &options);
+            let sizes = leaf_sizes(&tree);
+            assert_eq!(sizes.iter().sum::<usize>(), 64);
+            assert!(sizes.iter().all(|s| *s <= 2), "bins {}", bins);
+        }
+    }
+
+    #[test]
+    fn identical_boxes_still_split() {
+        let spheres = vec![
+            Sphere {
+                center: Point(Vec3::default()),
+                radius: 1.0,
+            };
+            20
+        ];
+        let tree = BboxTree::new(spheres);
+        let sizes = leaf_sizes(&tree);
+        assert_eq!(sizes.iter().sum::<usize>(), 20);
+        assert!(sizes
+            .iter()
+            .all(|s| *s <= BuildOptions::default().max_leaf_size));
+    }
+
+    #[test]
+    fn parallel_build_matches_serial() {
+        let spheres = (0..500)
+            .map(|idx| Sphere {
+                center: Point(Vec3::new(
+                    (idx * 7 % 31) as f64,
+                    (idx * 13 % 17) as f64,
+                    (idx * 3 % 11) as f64,
+                )),
+                radius: 0.5 + (idx % 5) as f64 * 0.1,
+            })
+            .collect::<Vec<_>>();
+        let serial = BuildOptions {
+            parallel_threshold: usize::MAX,
+            ..BuildOptions::default()
+        };
+        let parallel = BuildOptions {
+            parallel_threshold: 8,
+            ..BuildOptions::default()
+        };
+        let serial = BboxTree::with_options(spheres.clone(), &serial);
+        let parallel = BboxTree::with_options(spheres, &parallel);
+        assert_eq!(serial.bounds, parallel.bounds);
+        assert_eq!(serial.tree, parallel.tree);
+        assert_eq!(serial.leaves, parallel.leaves);
+    }
+
+    #[test]
+    fn finds_every_sphere() {
+        let tree = BboxTree::new(row_of_spheres(50));
+        let mut workspace = BboxTreeWorkspace::default();
+        for idx in 0..50 {
+            let x = 2.0 * idx as f64;
+            let r = Ray::new(Point(Vec3::new(x, 10.0, 0.0)), Vec3::new(0.0, -1.0, 0.0));
+            let (obj, hit) = tree
+                .hit_workspace(&mut workspace, &r, 0.0, f64::MAX)
+                .unwrap();
+            assert_eq!(obj.center.0.x(), x);
+            assert!((hit.t - 9.5).abs() < 1e-9);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::{fmt, io::Write, path::Path, str::FromStr};
+
+use serde::Serialize;
+
+use super::{BboxTree, TreeNode};
+
+/// A summary of the shape of a `BboxTree`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TreeStats {
+    /// Branches and leaves
+    pub nodes: usize,
+    pub leaves: usize,
+    /// Items the leaves point to
+    pub items: usize,
+    /// Items kept out of the tree because they have no bounding box
+    pub unbounded: usize,
+    /// Number of leaves at each depth, the root is at depth zero
+    pub depth_histogram: Vec<usize>,
+    /// Number of leaves holding each number of items
+    pub leaf_sizes: Vec<usize>,
+    /// See `BboxTree::sah_cost`
+    pub sah_cost: f64,
+    /// Surface area of the overlap between the children of a branch,
+    /// relative to the branch, averaged over every branch
+    pub overlap: f64,
+}
+
+impl TreeStats {
+    pub fn max_depth(&self) -> usize {
+        self.depth_histogram.len().saturating_sub(1)
+    }
+}
+
+impl fmt::Display for TreeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "nodes: {}, leaves: {}, items: {}, unbounded: {}",
+            self.nodes, self.leaves, self.items, self.unbounded
+        )?;
+        writeln!(f, "sah cost: {:.3}", self.sah_cost)?;
+        writeln!(f, "mean child overlap: {:.3}", self.overlap)?;
+        writeln!(f, "leaves by depth:")?;
+        for (depth, count) in self.depth_histogram.iter().enumerate() {
+            if *count > 0 {
+                writeln!(f, "  {:>4}: {}", depth, count)?;
+            }
+        }
+        writeln!(f, "leaves by size:")?;
+        for (size, count) in self.leaf_sizes.iter().enumerate() {
+            if *count > 0 {
+                writeln!(f, "  {:>4}: {}", size, count)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One node of the tree as exported.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NodeBox {
+    pub depth: usize,
+    /// Items in a leaf, zero for a branch
+    pub items: usize,
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+/// File formats the node boxes can be exported as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WireframeFormat {
+    /// Wavefront OBJ lines, one group per depth
+    Obj,
+    /// The stats followed by every node
+    Json,
+}
+
+impl WireframeFormat {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<WireframeFormat> {
+        let ext = path.as_ref().extension()?.to_str()?;
+        ext.parse().ok()
+    }
+}
+
+impl FromStr for WireframeFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "obj" => Ok(WireframeFormat::Obj),
+            "json" => Ok(WireframeFormat::Json),
+            _ => anyhow::bail!("unknown wireframe format {:?}, expected obj or json", s),
+        }
+    }
+}
+
+/// Surface area of the part of two nodes that overlaps.
+fn overlap_area(a: &TreeNode, b: &TreeNode) -> f64 {
+    let [x, y, z] = [0, 1, 2].map(|d| (a.max[d].min(b.max[d]) - a.min[d].max(b.min[d])) as f64);
+    if x < 0.0 || y < 0.0 || z < 0.0 {
+        return 0.0;
+    }
+    2.0 * (x * y + y * z + z * x)
+}
+
+impl<T> BboxTree<T> {
+    /// Every node with its depth, depth first from the root.
+    pub fn node_boxes(&self) -> Vec<NodeBox> {
+        let mut out = Vec::with_capacity(self.tree.len());
+        let mut stack = vec![];
+        if !self.tree.is_empty() {
+            stack.push((0, 0));
+        }
+        while let Some((idx, depth)) = stack.pop() {
+            let node = &self.tree[idx];
+            out.push(NodeBox {
+                depth,
+                items: node.count as usize,
+                min: node.min,
+                max: node.max,
+            });
+            if !node.is_leaf() {
+                stack.push((node.offset as usize, depth + 1));
+                stack.push((idx + 1, depth + 1));
+            }
+        }
+        out
+    }
+
+    pub fn stats(&self) -> TreeStats {
+        let mut stats = TreeStats {
+            nodes: self.tree.len(),
+            leaves: 0,
+            items: 0,
+            unbounded: 0,
+            depth_histogram: vec![],
+            leaf_sizes: vec![],
+            sah_cost: self.sah_cost(),
+            overlap: 0.0,
+        };
+        let mut branches = 0;
+        for node_box in self.node_boxes() {
+            if node_box.items == 0 {
+                continue;
+            }
+            stats.leaves += 1;
+            stats.items += node_box.items;
+            if stats.depth_histogram.len() <= node_box.depth {
+                stats.depth_histogram.resize(node_box.depth + 1, 0);
+            }
+            stats.depth_histogram[node_box.depth] += 1;
+            if stats.leaf_sizes.len() <= node_box.items {
+                stats.leaf_sizes.resize(node_box.items + 1, 0);
+            }
+            stats.leaf_sizes[node_box.items] += 1;
+        }
+        for (idx, node) in self.tree.iter().enumerate() {
+            let area = node.surface_area();
+            if node.is_leaf() || area <= 0.0 {
+                continue;
+            }
+            let (lhs, rhs) = (&self.tree[idx + 1], &self.tree[node.offset as usize]);
+            stats.overlap += overlap_area(lhs, rhs) / area;
+            branches += 1;
+        }
+        if branches > 0 {
+            stats.overlap /= branches as f64;
+        }
+        stats.unbounded = self.leaves.len() - stats.items;
+        stats
+    }
+
+    /// Write the node boxes, for looking at the tree in a model viewer.
+    pub fn write_wireframe<W: Write>(
+        &self,
+        mut w: W,
+        format: WireframeFormat,
+    ) -> anyhow::Result<()> {
+        let boxes = self.node_boxes();
+        match format {
+            WireframeFormat::Json => {
+                #[derive(Serialize)]
+                struct Export<'a> {
+                    stats: TreeStats,
+                    nodes: &'a [NodeBox],
+                }
+                let export = Export {
+                    stats: self.stats(),
+                    nodes: &boxes,
+                };
+                serde_json::to_writer_pretty(&mut w, &export)?;
+            }
+            WireframeFormat::Obj => {
+                // the twelve edges of a box, as pairs of corner indices where
+                // bit 0 picks max x, bit 1 max y and bit 2 max z
+                const EDGES: [(usize, usize); 12] = [
+                    (0, 1),
+                    (2, 3),
+                    (4, 5),
+                    (6, 7),
+                    (0, 2),
+                    (1, 3),
+                    (4, 6),
+                    (5, 7),
+                    (0, 4),
+                    (1, 5),
+                    (2, 6),
+                    (3, 7),
+                ];
+                let max_depth = boxes.iter().map(|b| b.depth).max().unwrap_or(0);
+                let mut first_vertex = 1;
+                for depth in 0..=max_depth {
+                    writeln!(w, "g depth_{}", depth)?;
+                    for b in boxes.iter().filter(|b| b.depth == depth) {
+                        for corner in 0..8 {
+                            let pick = |d: usize| {
+                                if corner & (1 << d) != 0 {
+                                    b.max[d]
+                                } else {
+                                    b.min[d]
+                                }
+                            };
+                            writeln!(w, "v {} {} {}", pick(0), pick(1), pick(2))?;
+                        }
+                        for (a, b) in EDGES.iter() {
+                            writeln!(w, "l {} {}", first_vertex + a, first_vertex + b)?;
+                        }
+                        first_vertex += 8;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bvh::bbox_tree::{tests::row_of_spheres, BuildOptions};
+
+    #[test]
+    fn stats_of_a_row() {
+        let options = BuildOptions {
+            max_leaf_size: 1,
+            ..BuildOptions::default()
+        };
+        let tree = BboxTree::with_options(row_of_spheres(8), &options);
+        let stats = tree.stats();
+        assert_eq!(stats.leaves, 8);
+        assert_eq!(stats.nodes, 15);
+        assert_eq!(stats.items, 8);
+        assert_eq!(stats.unbounded, 0);
+        assert_eq!(stats.max_depth(), 3);
+        assert_eq!(stats.depth_histogram, vec![0, 0, 0, 8]);
+        assert_eq!(stats.leaf_sizes, vec![0, 8]);
+        // spread out spheres don't overlap
+        assert_eq!(stats.overlap, 0.0);
+    }
+
+    #[test]
+    fn obj_wireframe() {
+        let tree = BboxTree::new(row_of_spheres(2));
+        let mut out = vec![];
+        tree.write_wireframe(&mut out, WireframeFormat::Obj)
+            .unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let nodes = tree.stats().nodes;
+        assert_eq!(
+            text.lines().filter(|l| l.starts_with("v ")).count(),
+            8 * nodes
+        );
+        assert_eq!(
+            text.lines().filter(|l| l.starts_with("l ")).count(),
+            12 * nodes
+        );
+        assert!(text.starts_with("g depth_0\n"));
+    }
+
+    #[test]
+    fn format_from_path() {
+        assert_eq!(
+            WireframeFormat::from_path("tree.OBJ"),
+            Some(WireframeFormat::Obj)
+        );
+        assert_eq!(
+            WireframeFormat::from_path("out/tree.json"),
+            Some(WireframeFormat::Json)
+        );
+        assert_eq!(WireframeFormat::from_path("tree"), None);
+    }
+}
@@ -0,0 +1,392 @@
+use super::{
+    count_node, count_primitive, next_down, next_up, BboxTree, BboxTreeWorkspace,
+    TraversalCounters, TreeNode,
+};
+use crate::{core::Ray, geometry::hittable::HitRecord};
+
+/// Widen the far distance a little, so rounding in the f32 slab test can't
+/// turn a graze into a miss.
+const FAR_SCALE: f32 = 1.0 + 4.0 * f32::EPSILON;
+
+/// Four children of a node, with their boxes stored one axis at a time so
+/// they can all be tested at once.
+#[derive(Debug, Clone, PartialEq)]
+#[repr(C, align(16))]
+pub(super) struct WideNode {
+    /// Minimum x, y and z then maximum x, y and z, one lane per child
+    bounds: [[f32; 4]; 6],
+    /// Index of a wide node, or the first item of a leaf
+    children: [u32; 4],
+    /// Number of items in a leaf, zero for a wide node
+    counts: [u16; 4],
+    /// Number of lanes in use, the rest have empty boxes
+    len: u8,
+}
+
+impl WideNode {
+    fn empty() -> WideNode {
+        let inf = [f32::INFINITY; 4];
+        let neg_inf = [f32::NEG_INFINITY; 4];
+        WideNode {
+            bounds: [inf, inf, inf, neg_inf, neg_inf, neg_inf],
+            children: [0; 4],
+            counts: [0; 4],
+            len: 0,
+        }
+    }
+}
+
+/// The ray in the precision the boxes are tested in.
+struct WideRay {
+    orig: [f32; 3],
+    inv_dir: [f32; 3],
+    dir_is_neg: [bool; 3],
+}
+
+impl WideRay {
+    fn new(ray: &Ray) -> WideRay {
+        let inv_dir = [0, 1, 2].map(|d| (1.0 / ray.direction[d]) as f32);
+        WideRay {
+            orig: [0, 1, 2].map(|d| ray.orig.0[d] as f32),
+            inv_dir,
+            dir_is_neg: inv_dir.map(|x| x < 0.0),
+        }
+    }
+}
+
+/// Slab test against all four boxes, returning the entry distances and a
+/// bit mask of the lanes that were hit.
+#[cfg_attr(all(target_arch = "x86_64", not(test)), allow(dead_code))]
+fn hit4_scalar(node: &WideNode, ray: &WideRay, t_min: f32, t_max: f32) -> ([f32; 4], u8) {
+    let mut near = [t_min; 4];
+    let mut far = [t_max; 4];
+    for axis in 0..3 {
+        let (lo, hi) = if ray.dir_is_neg[axis] {
+            (&node.bounds[axis + 3], &node.bounds[axis])
+        } else {
+            (&node.bounds[axis], &node.bounds[axis + 3])
+        };
+        for lane in 0..4 {
+            let t0 = (lo[lane] - ray.orig[axis]) * ray.inv_dir[axis];
+            let t1 = (hi[lane] - ray.orig[axis]) * ray.inv_dir[axis];
+            // written to match `maxps` and `minps`, a NaN keeps the old value
+            near[lane] = if t0 > near[lane] { t0 } else { near[lane] };
+            far[lane] = if t1 < far[lane] { t1 } else { far[lane] };
+        }
+    }
+    let mut mask = 0;
+    for lane in 0..4 {
+        if near[lane] <= far[lane] * FAR_SCALE {
+            mask |= 1 << lane;
+        }
+    }
+    (near, mask)
+}
+
+#[cfg(target_arch = "x86_64")]
+fn hit4_sse(node: &WideNode, ray: &WideRay, t_min: f32, t_max: f32) -> ([f32; 4], u8) {
+    use std::arch::x86_64::*;
+
+    // SAFETY: SSE is part of the x86_64 baseline, and every load and store
+    // is unaligned from a four lane array
+    unsafe {
+        let mut near = _mm_set1_ps(t_min);
+        let mut far = _mm_set1_ps(t_max);
+        for axis in 0..3 {
+            let (lo, hi) = if ray.dir_is_neg[axis] {
+                (&node.bounds[axis + 3], &node.bounds[axis])
+            } else {
+                (&node.bounds[axis], &node.bounds[axis + 3])
+            };
+            let orig = _mm_set1_ps(ray.orig[axis]);
+            let inv_dir = _mm_set1_ps(ray.inv_dir[axis]);
+            let t0 = _mm_mul_ps(_mm_sub_ps(_mm_loadu_ps(lo.as_ptr()), orig), inv_dir);
+            let t1 = _mm_mul_ps(_mm_sub_ps(_mm_loadu_ps(hi.as_ptr()), orig), inv_dir);
+            near = _mm_max_ps(t0, near);
+            far = _mm_min_ps(t1, far);
+        }
+        let far = _mm_mul_ps(far, _mm_set1_ps(FAR_SCALE));
+        let mask = _mm_movemask_ps(_mm_cmple_ps(near, far)) as u8;
+        let mut out = [0.0; 4];
+        _mm_storeu_ps(out.as_mut_ptr(), near);
+        (out, mask)
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+use hit4_scalar as hit4;
+#[cfg(target_arch = "x86_64")]
+use hit4_sse as hit4;
+
+fn surface_area(node: &TreeNode) -> f32 {
+    let [x, y, z] = [0, 1, 2].map(|d| node.max[d] - node.min[d]);
+    2.0 * (x * y + y * z + z * x)
+}
+
+/// Collapse a packed binary tree into wide nodes, root first.
+pub(super) fn collapse(tree: &[TreeNode]) -> Vec<WideNode> {
+    let mut wide = Vec::with_capacity(tree.len() / 2 + 1);
+    if !tree.is_empty() {
+        collapse_node(tree, 0, &mut wide);
+    }
+    wide
+}
+
+fn collapse_node(tree: &[TreeNode], idx: usize, wide: &mut Vec<WideNode>) -> u32 {
+    let mut slots = if tree[idx].is_leaf() {
+        vec![idx]
+    } else {
+        vec![idx + 1, tree[idx].offset as usize]
+    };
+    // pull up the grandchildren of the biggest branches until all four
+    // lanes are used
+    while slots.len() < 4 {
+        let biggest = slots
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| !tree[**node].is_leaf())
+            .max_by(|(_, a), (_, b)| surface_area(&tree[**a]).total_cmp(&surface_area(&tree[**b])))
+            .map(|(lane, _)| lane);
+        match biggest {
+            Some(lane) => {
+                let node = slots[lane];
+                slots[lane] = node + 1;
+                slots.insert(lane + 1, tree[node].offset as usize);
+            }
+            None => break,
+        }
+    }
+
+    let out = wide.len();
+    wide.push(WideNode::empty());
+    wide[out].len = slots.len() as u8;
+    for (lane, node_idx) in slots.into_iter().enumerate() {
+        let node = &tree[node_idx];
+        for d in 0..3 {
+            wide[out].bounds[d][lane] = node.min[d];
+            wide[out].bounds[d + 3][lane] = node.max[d];
+        }
+        if node.is_leaf() {
+            wide[out].children[lane] = node.offset;
+            wide[out].counts[lane] = node.count;
+        } else {
+            let child = collapse_node(tree, node_idx, wide);
+            wide[out].children[lane] = child;
+        }
+    }
+    out as u32
+}
+
+impl<T> BboxTree<T> {
+    /// Closest hit through the wide nodes, see `hit_workspace_with`.
+    pub(super) fn hit_wide<'s, R, F>(
+        &'s self,
+        workspace: &mut BboxTreeWorkspace,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        mut leaf_hit: F,
+    ) -> Option<(R, HitRecord)>
+    where
+        F: FnMut(&'s T, &Ray, f64, f64, &mut Option<TraversalCounters>) -> Option<(R, HitRecord)>,
+    {
+        let wide_ray = WideRay::new(ray);
+        workspace.stack.truncate(0);
+        workspace.stack.push(0);
+
+        let mut closest: Option<(R, HitRecord)> = None;
+        while let Some(node_idx) = workspace.stack.pop() {
+            let t_closest = closest.as_ref().map(|(_, r)| r.t).unwrap_or(t_max);
+            let node = &self.wide[node_idx];
+            count_node(&mut workspace.counters);
+            let (near, mask) = hit4(
+                node,
+                &wide_ray,
+                next_down(t_min as f32),
+                next_up(t_closest as f32),
+            );
+            if mask == 0 {
+                continue;
+            }
+
+            // nearest lanes first
+            let mut lanes = [0usize; 4];
+            let mut hits = 0;
+            for lane in 0..node.len as usize {
+                if mask & (1 << lane) != 0 {
+                    lanes[hits] = lane;
+                    hits += 1;
+                }
+            }
+            let lanes = &mut lanes[..hits];
+            lanes.sort_unstable_by(|a, b| near[*a].total_cmp(&near[*b]));
+
+            for lane in lanes.iter() {
+                let count = node.counts[*lane] as usize;
+                if count == 0 {
+                    continue;
+                }
+                let first = node.children[*lane] as usize;
+                for leaf in &self.leaves[first..first + count] {
+                    count_primitive(&mut workspace.counters);
+                    let t_closest = closest.as_ref().map(|(_, r)| r.t).unwrap_or(t_max);
+                    if let Some(hit) =
+                        leaf_hit(leaf, ray, t_min, t_closest, &mut workspace.counters)
+                    {
+                        closest = Some(hit)
+                    }
+                }
+            }
+            // the nearest branch goes on top of the stack
+            for lane in lanes.iter().rev() {
+                if node.counts[*lane] == 0 {
+                    workspace.stack.push(node.children[*lane] as usize);
+                }
+            }
+        }
+        closest
+    }
+
+    /// Any hit through the wide nodes, see `occluded_workspace`.
+    pub(super) fn occluded_wide<F>(
+        &self,
+        workspace: &mut BboxTreeWorkspace,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        mut leaf_occluded: F,
+    ) -> bool
+    where
+        F: FnMut(&T, &Ray, f64, f64, &mut Option<TraversalCounters>) -> bool,
+    {
+        let wide_ray = WideRay::new(ray);
+        let (t_min_f32, t_max_f32) = (next_down(t_min as f32), next_up(t_max as f32));
+        workspace.stack.truncate(0);
+        workspace.stack.push(0);
+
+        while let Some(node_idx) = workspace.stack.pop() {
+            let node = &self.wide[node_idx];
+            count_node(&mut workspace.counters);
+            let (_, mask) = hit4(node, &wide_ray, t_min_f32, t_max_f32);
+            for lane in 0..node.len as usize {
+                if mask & (1 << lane) == 0 {
+                    continue;
+                }
+                let count = node.counts[lane] as usize;
+                if count == 0 {
+                    workspace.stack.push(node.children[lane] as usize);
+                    continue;
+                }
+                let first = node.children[lane] as usize;
+                if self.leaves[first..first + count].iter().any(|leaf| {
+                    count_primitive(&mut workspace.counters);
+                    leaf_occluded(leaf, ray, t_min, t_max, &mut workspace.counters)
+                }) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha20Rng;
+
+    use super::*;
+    use crate::{
+        bvh::bbox_tree::{BboxTreeWorkspace, BuildOptions},
+        core::{Point, Vec3},
+        geometry::sphere::Sphere,
+    };
+
+    fn random_spheres<R: Rng>(rng: &mut R, n: usize) -> Vec<Sphere> {
+        (0..n)
+            .map(|_| Sphere {
+                center: Point(Vec3::random_range_with_rng(rng, -10.0, 10.0)),
+                radius: rng.gen_range(0.1..1.0),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn size_of_wide_node() {
+        assert_eq!(std::mem::size_of::<WideNode>(), 128);
+    }
+
+    #[test]
+    fn wide_matches_binary() {
+        let mut rng = ChaCha20Rng::seed_from_u64(7);
+        let spheres = random_spheres(&mut rng, 300);
+        let binary = BboxTree::new(spheres.clone());
+        let wide = BboxTree::with_options(
+            spheres,
+            &BuildOptions {
+                wide: true,
+                ..BuildOptions::default()
+            },
+        );
+        assert!(!wide.wide.is_empty());
+
+        let mut workspace = BboxTreeWorkspace::default();
+        for _ in 0..2000 {
+            let orig = Point(Vec3::random_range_with_rng(&mut rng, -12.0, 12.0));
+            let dir = Vec3::random_range_with_rng(&mut rng, -1.0, 1.0);
+            let r = Ray::new(orig, dir);
+            let expected = binary
+                .hit_workspace(&mut workspace, &r, 0.001, f64::MAX)
+                .map(|(obj, hit)| (obj.clone(), hit.t));
+            let got = wide
+                .hit_workspace(&mut workspace, &r, 0.001, f64::MAX)
+                .map(|(obj, hit)| (obj.clone(), hit.t));
+            assert_eq!(expected, got);
+        }
+    }
+
+    #[test]
+    fn single_leaf_tree() {
+        let spheres = vec![Sphere {
+            center: Point(Vec3::new(0.0, 0.0, -5.0)),
+            radius: 1.0,
+        }];
+        let mut tree = BboxTree::new(spheres);
+        tree.widen();
+        assert_eq!(tree.wide.len(), 1);
+        let mut workspace = BboxTreeWorkspace::default();
+        let r = Ray::new(Point(Vec3::default()), Vec3::new(0.0, 0.0, -1.0));
+        let (_, hit) = tree
+            .hit_workspace(&mut workspace, &r, 0.0, f64::MAX)
+            .unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-9);
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn sse_matches_scalar() {
+        let mut rng = ChaCha20Rng::seed_from_u64(11);
+        let spheres = random_spheres(&mut rng, 64);
+        let mut tree = BboxTree::new(spheres);
+        tree.widen();
+        for _ in 0..500 {
+            let orig = Point(Vec3::random_range_with_rng(&mut rng, -12.0, 12.0));
+            let mut dir = Vec3::random_range_with_rng(&mut rng, -1.0, 1.0);
+            // axis aligned rays divide by zero
+            if rng.gen_bool(0.2) {
+                dir[rng.gen_range(0..3)] = 0.0;
+            }
+            let r = WideRay::new(&Ray::new(orig, dir));
+            for node in &tree.wide {
+                let (near_a, mask_a) = hit4_scalar(node, &r, 0.0, f32::INFINITY);
+                let (near_b, mask_b) = hit4_sse(node, &r, 0.0, f32::INFINITY);
+                assert_eq!(mask_a, mask_b);
+                for lane in 0..4 {
+                    if mask_a & (1 << lane) != 0 {
+                        assert_eq!(near_a[lane], near_b[lane]);
+                    }
+                }
+            }
+        }
+    }
+}
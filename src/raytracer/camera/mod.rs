@@ -1,7 +1,10 @@
 use anyhow::Result;
 use rand::Rng;
 
-use crate::core::{math::random_in_unit_disk, Point, Ray, Vec3};
+use crate::core::{
+    math::{random_in_unit_disk, random_real},
+    Point, Ray, Vec3,
+};
 
 const DEFAULT_FOCAL_LENGTH: f64 = 1.0;
 
@@ -18,6 +21,7 @@ pub struct CameraBuilder {
     aperture: Option<f64>,
     vfov: Option<f64>,
     ratio: Option<AspectRatio>,
+    shutter: Option<(f64, f64)>,
 }
 
 impl CameraBuilder {
@@ -41,6 +45,11 @@ impl CameraBuilder {
         self.width = Some(width);
         self
     }
+    /// Rays are spread uniformly over the time the shutter is open
+    pub fn shutter(&mut self, open: f64, close: f64) -> &mut Self {
+        self.shutter = Some((open, close));
+        self
+    }
     pub fn build(self) -> Result<Camera> {
         let (dimm, ratio) = Dimmensions::from_two_of_three(self.height, self.width, self.ratio)?;
         let theta = degrees_to_radians(self.vfov.unwrap_or(DEFAULT_FOCAL_LENGTH));
@@ -49,12 +58,22 @@ impl CameraBuilder {
         // let height = 1.0;
         let width = ratio.as_float() * height;
         let lens_radius = self.aperture.map(|a| a / 2.0);
+        let (shutter_open, shutter_close) = self.shutter.unwrap_or((0.0, 0.0));
+        if shutter_close < shutter_open {
+            anyhow::bail!(
+                "shutter closes ({}) before it opens ({})",
+                shutter_close,
+                shutter_open
+            );
+        }
 
         Ok(Camera {
             height,
             width,
             focal_length: self.focal_length.unwrap_or(DEFAULT_FOCAL_LENGTH),
             lens_radius,
+            shutter_open,
+            shutter_close,
             dimm,
         })
     }
@@ -91,6 +110,8 @@ pub struct Camera {
     width: f64,
     lens_radius: Option<f64>,
     focal_length: f64,
+    shutter_open: f64,
+    shutter_close: f64,
     pub dimm: Dimmensions,
 }
 
@@ -124,9 +145,16 @@ impl Camera {
             - pos.origin.0
             - offset;
 
+        let time = if self.shutter_close > self.shutter_open {
+            random_real(rng, self.shutter_open, self.shutter_close)
+        } else {
+            self.shutter_open
+        };
+
         Ray {
             orig: Point(pos.origin.0 + offset),
             direction,
+            time,
         }
     }
 }
@@ -41,7 +41,7 @@ mod tests {
 
     #[test]
     fn non_nan_second() {
-        let a = std::f64::NAN;
+        let a = f64::NAN;
         let b = 50.1;
         let r = non_nan(a, b);
         assert_eq!(r, b);
@@ -49,8 +49,8 @@ mod tests {
 
     #[test]
     fn non_nan_both() {
-        let a = std::f64::NAN;
-        let b = std::f64::NAN;
+        let a = f64::NAN;
+        let b = f64::NAN;
         let r = non_nan(a, b);
         assert!(r.is_nan());
     }
@@ -72,14 +72,14 @@ mod tests {
     #[test]
     fn check_min_with_nans() {
         let a = 4.3;
-        let b = std::f64::NAN;
+        let b = f64::NAN;
         assert_eq!(fmin(a, b), a);
         assert_eq!(fmin(b, a), a);
     }
     #[test]
     fn check_min_nan_both() {
-        let a = std::f64::NAN;
-        let b = std::f64::NAN;
+        let a = f64::NAN;
+        let b = f64::NAN;
         assert!(fmin(a, b).is_nan())
     }
 
@@ -100,14 +100,14 @@ mod tests {
     #[test]
     fn check_max_with_nans() {
         let a = 4.3;
-        let b = std::f64::NAN;
+        let b = f64::NAN;
         assert_eq!(fmax(a, b), a);
         assert_eq!(fmax(b, a), a);
     }
     #[test]
     fn check_max_nan_both() {
-        let a = std::f64::NAN;
-        let b = std::f64::NAN;
+        let a = f64::NAN;
+        let b = f64::NAN;
         assert!(fmax(a, b).is_nan())
     }
 }
@@ -4,6 +4,7 @@ use super::fp::fmin;
 use crate::core::Vec3;
 pub type Real = f64;
 
+#[allow(dead_code)]
 #[inline]
 fn convert_spherical_to_cartesian(r: f64, theta: f64, phi: f64) -> Vec3 {
     let sin_phi = phi.sin();
@@ -67,6 +68,19 @@ pub fn random_unit_vector<R: Rng>(rng: &mut R) -> Vec3 {
     // random_on_unit_sphere_distribution(rng)
 }
 
+/// Express `local` in a frame where `w` is the z axis.
+pub fn from_local_frame(w: &Vec3, local: &Vec3) -> Vec3 {
+    let w = w.unit();
+    let a = if w.x().abs() > 0.9 {
+        Vec3::new(0.0, 1.0, 0.0)
+    } else {
+        Vec3::new(1.0, 0.0, 0.0)
+    };
+    let v = w.cross(&a).unit();
+    let u = w.cross(&v);
+    u.scale(local.x()) + v.scale(local.y()) + w.scale(local.z())
+}
+
 pub fn random_in_unit_disk<R: Rng>(rng: &mut R) -> Vec3 {
     loop {
         let p = Vec3::new(
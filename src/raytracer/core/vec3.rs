@@ -139,7 +139,7 @@ impl Vec3 {
     pub fn refract(&self, normal: &Vec3, etai_over_etat: Real) -> Vec3 {
         let cos_theta = fmin_one(self.scale(-1.0).dot(normal));
         let r_out_perp = (normal.scale(cos_theta) + *self).scale(etai_over_etat);
-        let r_out_parallel_mag = (1.0 - r_out_perp.length_squared()).abs().sqrt() * -1.0;
+        let r_out_parallel_mag = -(1.0 - r_out_perp.length_squared()).abs().sqrt();
         let r_out_parallel = normal.scale(r_out_parallel_mag);
         r_out_perp + r_out_parallel
     }
@@ -241,13 +241,19 @@ impl Default for Point {
 pub struct Ray {
     pub orig: Point,
     pub direction: Vec3,
+    #[serde(default)]
+    pub time: Real,
 }
 
 impl Ray {
     pub fn new(orig: Point, dir: Vec3) -> Ray {
+        Ray::with_time(orig, dir, 0.0)
+    }
+    pub fn with_time(orig: Point, dir: Vec3, time: Real) -> Ray {
         Ray {
             orig,
             direction: dir,
+            time,
         }
     }
     pub fn at(&self, t: Real) -> Point {
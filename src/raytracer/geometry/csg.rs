@@ -0,0 +1,313 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    hittable::{Geometry, HitRecord},
+    object::GeometricObject,
+};
+use crate::{
+    bvh::{
+        aabb::{surrounding_box, Aabb},
+        bbox_tree::TraversalCounters,
+    },
+    core::{
+        fp::{fmax, fmin},
+        Point, Ray, Vec3,
+    },
+};
+
+/// How far past a surface to look for the next one.
+const STEP_EPSILON: f64 = 1e-7;
+/// Give up on pathological shapes that keep reporting hits, the ray misses.
+const MAX_CROSSINGS: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CsgOperation {
+    Union,
+    Intersection,
+    /// The left shape with the right one carved out of it
+    Difference,
+}
+
+impl CsgOperation {
+    fn inside(&self, left: bool, right: bool) -> bool {
+        match self {
+            CsgOperation::Union => left || right,
+            CsgOperation::Intersection => left && right,
+            CsgOperation::Difference => left && !right,
+        }
+    }
+}
+
+/// Every surface crossing of a closed shape along a ray.
+///
+/// Not all shapes orient their normals outwards (`RectBox` sides all face
+/// the positive axis), so entering and leaving is told apart by parity.
+struct Crossings {
+    inside_at_start: bool,
+    hits: Vec<HitRecord>,
+}
+
+impl Crossings {
+    /// `None` when there are more than `MAX_CROSSINGS`, as the parity of a
+    /// cut short list can't be trusted.
+    fn find(
+        object: &GeometricObject,
+        ray: &Ray,
+        t_min: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> Option<Crossings> {
+        let mut hits = Vec::new();
+        let mut t = t_min;
+        while let Some(hit) = object.hit_counted(ray, t, f64::INFINITY, counters) {
+            if hits.len() == MAX_CROSSINGS {
+                log::trace!("csg child crossed more than {} times", MAX_CROSSINGS);
+                return None;
+            }
+            t = hit.t + STEP_EPSILON * hit.t.abs().max(1.0);
+            hits.push(hit);
+        }
+        Some(Crossings {
+            inside_at_start: hits.len() % 2 == 1,
+            hits,
+        })
+    }
+}
+
+/// Constructive solid geometry, combining two closed shapes.
+///
+/// The result is closed too, so nodes can be nested and filled with
+/// `Dielectric` or a medium.
+#[derive(Serialize, Deserialize)]
+pub struct Csg {
+    operation: CsgOperation,
+    left: Box<GeometricObject>,
+    right: Box<GeometricObject>,
+}
+
+impl Csg {
+    pub fn new<L: Into<GeometricObject>, R: Into<GeometricObject>>(
+        operation: CsgOperation,
+        left: L,
+        right: R,
+    ) -> Csg {
+        Csg {
+            operation,
+            left: Box::new(left.into()),
+            right: Box::new(right.into()),
+        }
+    }
+
+    pub fn children(&self) -> (&GeometricObject, &GeometricObject) {
+        (&self.left, &self.right)
+    }
+
+    pub fn union<L: Into<GeometricObject>, R: Into<GeometricObject>>(left: L, right: R) -> Csg {
+        Csg::new(CsgOperation::Union, left, right)
+    }
+
+    pub fn intersection<L: Into<GeometricObject>, R: Into<GeometricObject>>(
+        left: L,
+        right: R,
+    ) -> Csg {
+        Csg::new(CsgOperation::Intersection, left, right)
+    }
+
+    pub fn difference<L: Into<GeometricObject>, R: Into<GeometricObject>>(
+        left: L,
+        right: R,
+    ) -> Csg {
+        Csg::new(CsgOperation::Difference, left, right)
+    }
+}
+
+impl Geometry for Csg {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.hit_counted(ray, t_min, t_max, &mut None)
+    }
+
+    fn hit_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> Option<HitRecord> {
+        let left = Crossings::find(&self.left, ray, t_min, counters)?;
+        let right = Crossings::find(&self.right, ray, t_min, counters)?;
+
+        let mut in_left = left.inside_at_start;
+        let mut in_right = right.inside_at_start;
+        let inside = self.operation.inside(in_left, in_right);
+
+        let mut l = left.hits.into_iter().peekable();
+        let mut r = right.hits.into_iter().peekable();
+        loop {
+            let take_left = match (l.peek(), r.peek()) {
+                (Some(a), Some(b)) => a.t <= b.t,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+                (None, None) => return None,
+            };
+            let (hit, entering) = if take_left {
+                in_left = !in_left;
+                (l.next()?, in_left)
+            } else {
+                in_right = !in_right;
+                (r.next()?, in_right)
+            };
+            if hit.t > t_max {
+                return None;
+            }
+
+            if self.operation.inside(in_left, in_right) == inside {
+                continue;
+            }
+
+            // the recorded normal always faces the ray, so it points out of
+            // the child that was just entered
+            let mut outward = if entering {
+                hit.normal
+            } else {
+                hit.normal.scale(-1.0)
+            };
+            // carved out surfaces face into the removed shape
+            if !take_left && self.operation == CsgOperation::Difference {
+                outward.scale_mut(-1.0);
+            }
+            return Some(HitRecord::new(ray, hit.point, outward, hit.t, hit.u, hit.v));
+        }
+    }
+
+    fn occluded_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> bool {
+        self.hit_counted(ray, t_min, t_max, counters).is_some()
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let left = self.left.bounding_box();
+        let right = self.right.bounding_box();
+        match self.operation {
+            CsgOperation::Union => Some(surrounding_box(&left?, &right?)),
+            CsgOperation::Difference => left,
+            CsgOperation::Intersection => match (left, right) {
+                (Some(a), Some(b)) => Some(overlap(&a, &b)),
+                (a, b) => a.or(b),
+            },
+        }
+    }
+}
+
+fn overlap(lhs: &Aabb, rhs: &Aabb) -> Aabb {
+    let mut min = Vec3::default();
+    let mut max = Vec3::default();
+    for d in 0..3 {
+        min[d] = fmax(lhs.min.0[d], rhs.min.0[d]);
+        max[d] = fmin(lhs.max.0[d], rhs.max.0[d]).max(min[d]);
+    }
+    Aabb {
+        min: Point(min),
+        max: Point(max),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{rect::RectBox, sphere::Sphere};
+
+    fn sphere(x: f64, radius: f64) -> Sphere {
+        Sphere {
+            center: Point(Vec3::new(x, 0.0, -5.0)),
+            radius,
+        }
+    }
+
+    fn along_x() -> Ray {
+        Ray::new(Point(Vec3::new(-10.0, 0.0, -5.0)), Vec3::new(1.0, 0.0, 0.0))
+    }
+
+    #[test]
+    fn union_skips_inner_surfaces() {
+        let csg = Csg::union(sphere(-0.5, 1.0), sphere(0.5, 1.0));
+        let r = along_x();
+        let hit = csg.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 8.5).abs() < 1e-9);
+        // start inside the left sphere, the next surface is the far side of
+        // the right one
+        let hit = csg.hit(&r, 9.5, f64::MAX).unwrap();
+        assert!((hit.t - 11.5).abs() < 1e-9);
+        assert!(!hit.front_face);
+    }
+
+    #[test]
+    fn intersection_lens() {
+        let csg = Csg::intersection(sphere(-0.5, 1.0), sphere(0.5, 1.0));
+        let r = along_x();
+        let hit = csg.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 9.5).abs() < 1e-9);
+        assert!(hit.front_face);
+        let hit = csg.hit(&r, 9.6, f64::MAX).unwrap();
+        assert!((hit.t - 10.5).abs() < 1e-9);
+        assert!(!hit.front_face);
+
+        let bbox = csg.bounding_box().unwrap();
+        assert!((bbox.min.0.x() + 0.5).abs() < 1e-9);
+        assert!((bbox.max.0.x() - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn sphere_minus_box() {
+        let carve = RectBox::new(
+            Point(Vec3::new(-2.0, -2.0, -7.0)),
+            Point(Vec3::new(0.0, 2.0, -3.0)),
+        );
+        let csg = Csg::difference(sphere(0.0, 1.0), carve);
+        let r = along_x();
+        // the left half is gone, we enter on the flat cut
+        let hit = csg.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 10.0).abs() < 1e-9);
+        assert!(hit.front_face);
+        assert!((hit.normal - Vec3::new(-1.0, 0.0, 0.0)).near_zero());
+
+        // a ray that only touches the removed part misses
+        let r = Ray::new(Point(Vec3::new(-0.5, 5.0, -5.0)), Vec3::new(0.0, -1.0, 0.0));
+        assert!(csg.hit(&r, 0.0, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn hollow_shell() {
+        // glass with a hollow center, like the negative radius sphere trick
+        let csg = Csg::difference(sphere(0.0, 1.0), sphere(0.0, 0.9));
+        let r = along_x();
+        let mut t = 0.0;
+        let mut faces = Vec::new();
+        while let Some(hit) = csg.hit(&r, t, f64::MAX) {
+            faces.push(hit.front_face);
+            t = hit.t + 1e-6;
+        }
+        assert_eq!(faces, vec![true, false, true, false]);
+    }
+
+    #[test]
+    fn too_many_crossings_miss() {
+        use crate::geometry::triangle::{MeshData, TriangleMesh};
+        // a stack of panes across the ray, more than can be tracked
+        let mut mesh = MeshData::default();
+        for i in 0..=MAX_CROSSINGS {
+            let x = -2.0 + i as f64 * 0.01;
+            let base = mesh.vertices.len();
+            mesh.vertices.push(Point(Vec3::new(x, -1.0, -6.0)));
+            mesh.vertices.push(Point(Vec3::new(x, 1.0, -6.0)));
+            mesh.vertices.push(Point(Vec3::new(x, 0.0, -4.0)));
+            mesh.faces.push([base, base + 1, base + 2]);
+        }
+        let panes = TriangleMesh::new(mesh).unwrap();
+        let csg = Csg::union(sphere(0.0, 1.0), panes);
+        assert!(csg.hit(&along_x(), 0.0, f64::MAX).is_none());
+    }
+}
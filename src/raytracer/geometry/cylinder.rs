@@ -0,0 +1,284 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use super::hittable::{Geometry, HitRecord};
+use crate::{
+    bvh::aabb::Aabb,
+    core::{Point, Ray, Vec3},
+};
+
+const PARALLEL_EPSILON: f64 = 1e-12;
+
+/// Angle around the y axis, as a texture coordinate.
+fn around_y(x: f64, z: f64) -> f64 {
+    ((-z).atan2(x) + PI) / (2.0 * PI)
+}
+
+/// Intersect the cap at `y` (in local space) of radius `radius`.
+fn hit_cap(
+    ray: &Ray,
+    origin: &Vec3,
+    y: f64,
+    radius: f64,
+    normal_y: f64,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, Vec3, f64, f64)> {
+    if ray.direction.y().abs() < PARALLEL_EPSILON {
+        return None;
+    }
+    let t = (y - origin.y()) / ray.direction.y();
+    if t < t_min || t > t_max {
+        return None;
+    }
+    let x = origin.x() + t * ray.direction.x();
+    let z = origin.z() + t * ray.direction.z();
+    let r2 = x * x + z * z;
+    if r2 > radius * radius {
+        return None;
+    }
+    let u = around_y(x, z);
+    let v = r2.sqrt() / radius;
+    Some((t, Vec3::new(0.0, normal_y, 0.0), u, v))
+}
+
+fn y_bbox(base: &Point, radius: f64, height: f64) -> Aabb {
+    Aabb {
+        min: Point(base.0 + Vec3::new(-radius, 0.0, -radius)),
+        max: Point(base.0 + Vec3::new(radius, height, radius)),
+    }
+}
+
+/// Keep the closest of two optional hits.
+fn closer(
+    a: Option<(f64, Vec3, f64, f64)>,
+    b: Option<(f64, Vec3, f64, f64)>,
+) -> Option<(f64, Vec3, f64, f64)> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(if a.0 <= b.0 { a } else { b }),
+        (a, b) => a.or(b),
+    }
+}
+
+/// A cylinder standing on `base`, around the y axis.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Cylinder {
+    pub base: Point,
+    pub radius: f64,
+    pub height: f64,
+    /// Close the top and bottom with disks
+    #[serde(default)]
+    pub capped: bool,
+}
+
+impl Cylinder {
+    pub fn new(base: Point, radius: f64, height: f64, capped: bool) -> Cylinder {
+        Cylinder {
+            base,
+            radius,
+            height,
+            capped,
+        }
+    }
+
+    fn hit_side(
+        &self,
+        ray: &Ray,
+        o: &Vec3,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(f64, Vec3, f64, f64)> {
+        let d = &ray.direction;
+        let a = d.x() * d.x() + d.z() * d.z();
+        if a < PARALLEL_EPSILON {
+            return None;
+        }
+        let half_b = o.x() * d.x() + o.z() * d.z();
+        let c = o.x() * o.x() + o.z() * o.z() - self.radius * self.radius;
+        let discriminant = half_b * half_b - a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let sqrt_d = discriminant.sqrt();
+        for t in [(-half_b - sqrt_d) / a, (-half_b + sqrt_d) / a] {
+            if t < t_min || t > t_max {
+                continue;
+            }
+            let y = o.y() + t * d.y();
+            if y < 0.0 || y > self.height {
+                continue;
+            }
+            let x = o.x() + t * d.x();
+            let z = o.z() + t * d.z();
+            let normal = Vec3::new(x / self.radius, 0.0, z / self.radius);
+            return Some((t, normal, around_y(x, z), y / self.height));
+        }
+        None
+    }
+}
+
+impl Geometry for Cylinder {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let o = ray.orig.0 - self.base.0;
+        let mut closest = self.hit_side(ray, &o, t_min, t_max);
+        if self.capped {
+            let t_closest = closest.as_ref().map(|h| h.0).unwrap_or(t_max);
+            closest = closer(
+                closest,
+                hit_cap(ray, &o, 0.0, self.radius, -1.0, t_min, t_closest),
+            );
+            let t_closest = closest.as_ref().map(|h| h.0).unwrap_or(t_max);
+            closest = closer(
+                closest,
+                hit_cap(ray, &o, self.height, self.radius, 1.0, t_min, t_closest),
+            );
+        }
+        let (t, normal, u, v) = closest?;
+        Some(HitRecord::new(ray, ray.at(t), normal, t, u, v))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(y_bbox(&self.base, self.radius, self.height))
+    }
+}
+
+/// A cone with its circular base on `base` and its apex `height` above it.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Cone {
+    pub base: Point,
+    pub radius: f64,
+    pub height: f64,
+    /// Close the bottom with a disk
+    #[serde(default)]
+    pub capped: bool,
+}
+
+impl Cone {
+    pub fn new(base: Point, radius: f64, height: f64, capped: bool) -> Cone {
+        Cone {
+            base,
+            radius,
+            height,
+            capped,
+        }
+    }
+
+    fn hit_side(
+        &self,
+        ray: &Ray,
+        o: &Vec3,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(f64, Vec3, f64, f64)> {
+        let d = &ray.direction;
+        // x^2 + z^2 = k (h - y)^2
+        let k = (self.radius / self.height).powi(2);
+        let h = self.height - o.y();
+        let a = d.x() * d.x() + d.z() * d.z() - k * d.y() * d.y();
+        let half_b = o.x() * d.x() + o.z() * d.z() + k * h * d.y();
+        let c = o.x() * o.x() + o.z() * o.z() - k * h * h;
+
+        let roots = if a.abs() < PARALLEL_EPSILON {
+            // the ray is parallel to the slope, there is only one crossing
+            if half_b.abs() < PARALLEL_EPSILON {
+                return None;
+            }
+            let t = -c / (2.0 * half_b);
+            [t, t]
+        } else {
+            let discriminant = half_b * half_b - a * c;
+            if discriminant < 0.0 {
+                return None;
+            }
+            let sqrt_d = discriminant.sqrt();
+            let (t0, t1) = ((-half_b - sqrt_d) / a, (-half_b + sqrt_d) / a);
+            [t0.min(t1), t0.max(t1)]
+        };
+
+        for t in roots {
+            if t < t_min || t > t_max {
+                continue;
+            }
+            let y = o.y() + t * d.y();
+            if y < 0.0 || y > self.height {
+                continue;
+            }
+            let x = o.x() + t * d.x();
+            let z = o.z() + t * d.z();
+            let normal = Vec3::new(x, k * (self.height - y), z).unit();
+            return Some((t, normal, around_y(x, z), y / self.height));
+        }
+        None
+    }
+}
+
+impl Geometry for Cone {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let o = ray.orig.0 - self.base.0;
+        let mut closest = self.hit_side(ray, &o, t_min, t_max);
+        if self.capped {
+            let t_closest = closest.as_ref().map(|h| h.0).unwrap_or(t_max);
+            closest = closer(
+                closest,
+                hit_cap(ray, &o, 0.0, self.radius, -1.0, t_min, t_closest),
+            );
+        }
+        let (t, normal, u, v) = closest?;
+        Some(HitRecord::new(ray, ray.at(t), normal, t, u, v))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(y_bbox(&self.base, self.radius, self.height))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cylinder(capped: bool) -> Cylinder {
+        Cylinder::new(Point(Vec3::new(0.0, -1.0, -5.0)), 1.0, 2.0, capped)
+    }
+
+    #[test]
+    fn hit_cylinder_side() {
+        let r = Ray::new(Point(Vec3::default()), Vec3::new(0.0, 0.0, -1.0));
+        let hit = cylinder(false).hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-9);
+        assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).near_zero());
+        assert!((hit.v - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn cylinder_caps() {
+        // looking straight down the axis
+        let r = Ray::new(Point(Vec3::new(0.0, 5.0, -5.0)), Vec3::new(0.0, -1.0, 0.0));
+        assert!(cylinder(false).hit(&r, 0.0, f64::MAX).is_none());
+        let hit = cylinder(true).hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-9);
+        assert!(hit.front_face);
+        assert!((hit.normal - Vec3::new(0.0, 1.0, 0.0)).near_zero());
+    }
+
+    #[test]
+    fn hit_cone() {
+        let cone = Cone::new(Point(Vec3::new(0.0, 0.0, -5.0)), 1.0, 2.0, true);
+        // halfway up the radius is 0.5
+        let r = Ray::new(Point(Vec3::new(0.0, 1.0, 0.0)), Vec3::new(0.0, 0.0, -1.0));
+        let hit = cone.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-9);
+        let expected = Vec3::new(0.0, 0.5, 1.0).unit();
+        assert!((hit.normal - expected).near_zero());
+
+        // the cap from below
+        let r = Ray::new(Point(Vec3::new(0.2, -3.0, -5.0)), Vec3::new(0.0, 1.0, 0.0));
+        let hit = cone.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-9);
+        assert!((hit.normal - Vec3::new(0.0, -1.0, 0.0)).near_zero());
+
+        // above the apex
+        let r = Ray::new(Point(Vec3::new(0.0, 2.5, 0.0)), Vec3::new(0.0, 0.0, -1.0));
+        assert!(cone.hit(&r, 0.0, f64::MAX).is_none());
+    }
+}
@@ -0,0 +1,101 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use super::hittable::{Geometry, HitRecord};
+use crate::{
+    bvh::aabb::Aabb,
+    core::{math::from_local_frame, Point, Ray, Vec3},
+};
+
+const BBOX_WIDTH: f64 = 0.0001;
+const PARALLEL_EPSILON: f64 = 1e-12;
+
+/// A flat disk facing `normal`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Disk {
+    pub center: Point,
+    pub normal: Vec3,
+    pub radius: f64,
+}
+
+impl Disk {
+    pub fn new(center: Point, normal: Vec3, radius: f64) -> Disk {
+        Disk {
+            center,
+            normal: normal.unit(),
+            radius,
+        }
+    }
+}
+
+impl Geometry for Disk {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let n = self.normal.unit();
+        let denom = n.dot(&ray.direction);
+        if denom.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+        let t = n.dot(&(self.center.0 - ray.orig.0)) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let point = ray.at(t);
+        let offset = point.0 - self.center.0;
+        let r2 = offset.length_squared();
+        if r2 > self.radius * self.radius {
+            return None;
+        }
+
+        // polar coordinates in the plane of the disk
+        let tangent = from_local_frame(&n, &Vec3::new(1.0, 0.0, 0.0));
+        let bitangent = from_local_frame(&n, &Vec3::new(0.0, 1.0, 0.0));
+        let phi = offset.dot(&bitangent).atan2(offset.dot(&tangent)) + PI;
+        let u = phi / (2.0 * PI);
+        let v = r2.sqrt() / self.radius;
+        Some(HitRecord::new(ray, point, n, t, u, v))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let n = self.normal.unit();
+        let mut extent = Vec3::default();
+        for d in 0..3 {
+            extent[d] = self.radius * (1.0 - n[d] * n[d]).max(0.0).sqrt() + BBOX_WIDTH;
+        }
+        Some(Aabb {
+            min: Point(self.center.0 - extent),
+            max: Point(self.center.0 + extent),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hit_tilted_disk() {
+        let disk = Disk::new(
+            Point(Vec3::new(0.0, 0.0, -3.0)),
+            Vec3::new(0.0, 1.0, 1.0),
+            1.0,
+        );
+        let r = Ray::new(Point(Vec3::default()), Vec3::new(0.0, 0.0, -1.0));
+        let hit = disk.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-9);
+        assert!(hit.v.abs() < 1e-9);
+        assert!(hit.front_face);
+
+        // inside the bounding box, but outside the rim
+        let r = Ray::new(Point(Vec3::new(0.9, 0.6, 0.0)), Vec3::new(0.0, 0.0, -1.0));
+        assert!(disk.hit(&r, 0.0, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn tilted_disk_bounds() {
+        let disk = Disk::new(Point(Vec3::default()), Vec3::new(0.0, 1.0, 0.0), 2.0);
+        let b = disk.bounding_box().unwrap();
+        assert!((b.max.0.x() - 2.0).abs() < 1e-3);
+        assert!(b.max.0.y() > 0.0 && b.max.0.y() < 1e-3);
+    }
+}
@@ -0,0 +1,311 @@
+use std::{convert::TryFrom, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    hittable::{Geometry, HitRecord},
+    triangle::intersect,
+};
+use crate::{
+    bvh::aabb::Aabb,
+    core::{Point, Ray, Vec3},
+};
+
+const BBOX_WIDTH: f64 = 0.0001;
+
+/// Where a `Heightfield` comes from, this is what gets saved with a scene.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HeightfieldSource {
+    /// Grayscale image, each pixel is one height sample
+    pub path: PathBuf,
+    /// Corner with the smallest x and z, black pixels sit at its height
+    pub origin: Point,
+    /// Extent along x, across the image
+    pub width: f64,
+    /// Extent along z, down the image
+    pub depth: f64,
+    /// Height of a white pixel
+    pub height_scale: f64,
+}
+
+/// Terrain over the xz plane, sampled from an image.
+///
+/// Every four neighbouring samples make a cell of two triangles, which a ray
+/// walks through in order so only the cells below it get tested.
+#[derive(Deserialize)]
+#[serde(try_from = "HeightfieldSource")]
+pub struct Heightfield {
+    source: HeightfieldSource,
+    columns: usize,
+    rows: usize,
+    heights: Vec<f64>,
+    normals: Vec<Vec3>,
+    min_height: f64,
+    max_height: f64,
+}
+
+impl Heightfield {
+    pub fn new(source: HeightfieldSource) -> anyhow::Result<Heightfield> {
+        let img = image::open(&source.path)?;
+        Heightfield::from_image(source, &img)
+    }
+
+    /// Build from an image already in memory, `source.path` is only kept for
+    /// saving.
+    pub fn from_image(
+        source: HeightfieldSource,
+        img: &image::DynamicImage,
+    ) -> anyhow::Result<Heightfield> {
+        let luma = img.to_luma16();
+        let (columns, rows) = (luma.width() as usize, luma.height() as usize);
+        if columns < 2 || rows < 2 {
+            anyhow::bail!(
+                "heightfield {:?} needs at least 2x2 samples, got {}x{}",
+                source.path,
+                columns,
+                rows
+            );
+        }
+        let heights = luma
+            .pixels()
+            .map(|p| source.origin.0.y() + source.height_scale * p.0[0] as f64 / 65535.0)
+            .collect::<Vec<_>>();
+
+        let min_height = heights.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max_height = heights.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let mut field = Heightfield {
+            source,
+            columns,
+            rows,
+            heights,
+            normals: vec![],
+            min_height,
+            max_height,
+        };
+        field.normals = (0..rows)
+            .flat_map(|j| (0..columns).map(move |i| (i, j)))
+            .map(|(i, j)| field.vertex_normal(i, j))
+            .collect();
+        Ok(field)
+    }
+
+    fn cell_size(&self) -> (f64, f64) {
+        (
+            self.source.width / (self.columns - 1) as f64,
+            self.source.depth / (self.rows - 1) as f64,
+        )
+    }
+
+    fn height(&self, i: usize, j: usize) -> f64 {
+        self.heights[j * self.columns + i]
+    }
+
+    fn vertex(&self, i: usize, j: usize) -> Point {
+        let (dx, dz) = self.cell_size();
+        let o = self.source.origin.0;
+        Point(Vec3::new(
+            o.x() + i as f64 * dx,
+            self.height(i, j),
+            o.z() + j as f64 * dz,
+        ))
+    }
+
+    /// Central differences, one sided on the border.
+    fn vertex_normal(&self, i: usize, j: usize) -> Vec3 {
+        let (dx, dz) = self.cell_size();
+        let (i0, i1) = (i.saturating_sub(1), (i + 1).min(self.columns - 1));
+        let (j0, j1) = (j.saturating_sub(1), (j + 1).min(self.rows - 1));
+        let slope_x = (self.height(i1, j) - self.height(i0, j)) / ((i1 - i0) as f64 * dx);
+        let slope_z = (self.height(i, j1) - self.height(i, j0)) / ((j1 - j0) as f64 * dz);
+        Vec3::new(-slope_x, 1.0, -slope_z).unit()
+    }
+
+    fn hit_cell(&self, ray: &Ray, i: usize, j: usize, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        // both triangles wind so their front faces point up
+        let corners = [
+            [(i, j), (i, j + 1), (i + 1, j)],
+            [(i + 1, j + 1), (i + 1, j), (i, j + 1)],
+        ];
+        let mut closest: Option<HitRecord> = None;
+        for tri in corners.iter() {
+            let t_closest = closest.as_ref().map(|r| r.t).unwrap_or(t_max);
+            let [p0, p1, p2] = [
+                self.vertex(tri[0].0, tri[0].1),
+                self.vertex(tri[1].0, tri[1].1),
+                self.vertex(tri[2].0, tri[2].1),
+            ];
+            let (t, b1, b2) = match intersect(ray, &p0, &p1, &p2, t_min, t_closest) {
+                Some(hit) => hit,
+                None => continue,
+            };
+            let b0 = 1.0 - b1 - b2;
+
+            let point = ray.at(t);
+            let u = (point.0.x() - self.source.origin.0.x()) / self.source.width;
+            let v = 1.0 - (point.0.z() - self.source.origin.0.z()) / self.source.depth;
+            let geometric = (p1.0 - p0.0).cross(&(p2.0 - p0.0)).unit();
+            let mut record = HitRecord::new(ray, point, geometric, t, u, v);
+
+            let normal = |(i, j): (usize, usize)| self.normals[j * self.columns + i];
+            let mut shading =
+                (normal(tri[0]).scale(b0) + normal(tri[1]).scale(b1) + normal(tri[2]).scale(b2))
+                    .unit();
+            if shading.dot(&record.normal) < 0.0 {
+                shading.scale_mut(-1.0);
+            }
+            record.normal = shading;
+            closest = Some(record);
+        }
+        closest
+    }
+}
+
+impl TryFrom<HeightfieldSource> for Heightfield {
+    type Error = anyhow::Error;
+
+    fn try_from(source: HeightfieldSource) -> Result<Self, Self::Error> {
+        Heightfield::new(source)
+    }
+}
+
+impl Serialize for Heightfield {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.source.serialize(serializer)
+    }
+}
+
+impl Geometry for Heightfield {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (t_enter, t_exit) = self.bounding_box()?.clip(ray, t_min, t_max)?;
+        let (dx, dz) = self.cell_size();
+        let o = self.source.origin.0;
+        let start = ray.at(t_enter).0;
+
+        let last_column = (self.columns - 2) as f64;
+        let last_row = (self.rows - 2) as f64;
+        let mut i = ((start.x() - o.x()) / dx).floor().clamp(0.0, last_column) as usize;
+        let mut j = ((start.z() - o.z()) / dz).floor().clamp(0.0, last_row) as usize;
+
+        // grid DDA, `next_*` is the ray parameter of the next cell boundary
+        // along each axis and `delta_*` the distance between boundaries
+        let d = ray.direction;
+        let (step_i, delta_i, mut next_i) = if d.x() > 0.0 {
+            let edge = o.x() + (i + 1) as f64 * dx;
+            (1, dx / d.x(), (edge - ray.orig.0.x()) / d.x())
+        } else if d.x() < 0.0 {
+            let edge = o.x() + i as f64 * dx;
+            (-1, -dx / d.x(), (edge - ray.orig.0.x()) / d.x())
+        } else {
+            (0, f64::INFINITY, f64::INFINITY)
+        };
+        let (step_j, delta_j, mut next_j) = if d.z() > 0.0 {
+            let edge = o.z() + (j + 1) as f64 * dz;
+            (1, dz / d.z(), (edge - ray.orig.0.z()) / d.z())
+        } else if d.z() < 0.0 {
+            let edge = o.z() + j as f64 * dz;
+            (-1, -dz / d.z(), (edge - ray.orig.0.z()) / d.z())
+        } else {
+            (0, f64::INFINITY, f64::INFINITY)
+        };
+
+        loop {
+            if let Some(hit) = self.hit_cell(ray, i, j, t_min, t_max) {
+                return Some(hit);
+            }
+            if next_i < next_j {
+                if next_i > t_exit {
+                    return None;
+                }
+                next_i += delta_i;
+                i = match i.checked_add_signed(step_i) {
+                    Some(i) if i < self.columns - 1 => i,
+                    _ => return None,
+                };
+            } else {
+                if next_j > t_exit {
+                    return None;
+                }
+                next_j += delta_j;
+                j = match j.checked_add_signed(step_j) {
+                    Some(j) if j < self.rows - 1 => j,
+                    _ => return None,
+                };
+            }
+        }
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let o = self.source.origin.0;
+        Some(Aabb {
+            min: Point(Vec3::new(o.x(), self.min_height - BBOX_WIDTH, o.z())),
+            max: Point(Vec3::new(
+                o.x() + self.source.width,
+                self.max_height + BBOX_WIDTH,
+                o.z() + self.source.depth,
+            )),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ridge along z, peaking in the middle column.
+    fn ridge() -> Heightfield {
+        let img =
+            image::GrayImage::from_fn(3, 4, |x, _| image::Luma([if x == 1 { 255 } else { 0 }]));
+        let source = HeightfieldSource {
+            path: PathBuf::from("ridge.png"),
+            origin: Point(Vec3::new(-1.0, 0.0, 0.0)),
+            width: 2.0,
+            depth: 3.0,
+            height_scale: 1.0,
+        };
+        Heightfield::from_image(source, &image::DynamicImage::ImageLuma8(img)).unwrap()
+    }
+
+    #[test]
+    fn hit_from_above() {
+        let field = ridge();
+        let r = Ray::new(Point(Vec3::new(0.5, 5.0, 1.2)), Vec3::new(0.0, -1.0, 0.0));
+        let hit = field.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-9);
+        assert!(hit.front_face);
+        assert!((hit.u - 0.75).abs() < 1e-9);
+        assert!((hit.v - 0.6).abs() < 1e-9);
+
+        let b = field.bounding_box().unwrap();
+        assert!((b.max.0.y() - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn walks_cells_in_order() {
+        let field = ridge();
+        // skims across the low side and hits the slope facing it
+        let r = Ray::new(Point(Vec3::new(-3.0, 0.25, 1.5)), Vec3::new(1.0, 0.0, 0.0));
+        let hit = field.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.point.0.x() + 0.75).abs() < 1e-9);
+        assert!(hit.normal.x() < 0.0 && hit.normal.y() > 0.0);
+
+        // going the other way hits the far slope first
+        let r = Ray::new(Point(Vec3::new(3.0, 0.25, 1.5)), Vec3::new(-1.0, 0.0, 0.0));
+        let hit = field.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.point.0.x() - 0.75).abs() < 1e-9);
+
+        // and over the top misses
+        let r = Ray::new(Point(Vec3::new(-3.0, 1.5, 1.5)), Vec3::new(1.0, 0.0, 0.2));
+        assert!(field.hit(&r, 0.0, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn normals_are_smooth() {
+        let field = ridge();
+        // the peak is shared by both slopes, its normal points straight up
+        assert!((field.vertex_normal(1, 2) - Vec3::new(0.0, 1.0, 0.0)).near_zero());
+        let r = Ray::new(Point(Vec3::new(-0.1, 5.0, 1.5)), Vec3::new(0.0, -1.0, 0.0));
+        let hit = field.hit(&r, 0.0, f64::MAX).unwrap();
+        let flat = Vec3::new(-1.0, 1.0, 0.0).unit();
+        assert!(hit.normal.y() > flat.y());
+    }
+}
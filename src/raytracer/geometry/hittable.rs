@@ -1,5 +1,7 @@
+use rand::Rng;
+
 use crate::{
-    bvh::aabb::Aabb,
+    bvh::{aabb::Aabb, bbox_tree::TraversalCounters},
     core::{Point, Ray, Vec3},
 };
 
@@ -40,9 +42,48 @@ impl HitRecord {
 pub trait Geometry {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord>;
     fn bounding_box(&self) -> Option<Aabb>;
+
+    /// Whether anything is hit in `[t_min, t_max]`, for shadow rays that
+    /// don't care what or where. Override it when that can be answered
+    /// without building the whole `HitRecord`.
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.hit(ray, t_min, t_max).is_some()
+    }
+
+    /// `hit`, adding the work done in any tree nested inside the geometry to
+    /// `counters`. Only geometry that holds a tree, or wraps geometry that
+    /// might, needs to override it.
+    fn hit_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        _counters: &mut Option<TraversalCounters>,
+    ) -> Option<HitRecord> {
+        self.hit(ray, t_min, t_max)
+    }
+
+    /// `occluded`, counted like `hit_counted`.
+    fn occluded_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        _counters: &mut Option<TraversalCounters>,
+    ) -> bool {
+        self.occluded(ray, t_min, t_max)
+    }
 }
 
 pub trait Hittable {
     type Leaf;
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(&Self::Leaf, HitRecord)>;
 }
+
+/// Shapes that can be sampled by direction, for lights.
+pub trait SolidAngleSampler: Geometry {
+    /// Pick a direction from `origin` toward a point on the surface.
+    fn sample_direction<R: Rng>(&self, rng: &mut R, origin: &Point) -> Vec3;
+    /// Density of `sample_direction` picking `direction`, with respect to solid angle.
+    fn direction_pdf(&self, origin: &Point, direction: &Vec3) -> f64;
+}
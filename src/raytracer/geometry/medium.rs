@@ -0,0 +1,203 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    hittable::{Geometry, HitRecord},
+    object::GeometricObject,
+};
+use crate::{
+    bvh::aabb::Aabb,
+    core::{Ray, Vec3},
+};
+
+const BOUNDARY_EPSILON: f64 = 0.0001;
+
+/// A volume of constant density filling a closed, convex boundary.
+///
+/// Media are not surfaces, `hit` never reports anything. The scene keeps them
+/// apart from the other objects and the integrator samples a free-flight
+/// distance through them with `sample_distance`.
+#[derive(Serialize, Deserialize)]
+pub struct ConstantMedium {
+    boundary: Box<GeometricObject>,
+    density: f64,
+}
+
+impl ConstantMedium {
+    pub fn new<G: Into<GeometricObject>>(boundary: G, density: f64) -> ConstantMedium {
+        ConstantMedium {
+            boundary: Box::new(boundary.into()),
+            density,
+        }
+    }
+
+    pub fn boundary(&self) -> &GeometricObject {
+        &self.boundary
+    }
+
+    pub fn density(&self) -> f64 {
+        self.density
+    }
+
+    /// The part of `[t_min, t_max]` where the ray is inside the boundary.
+    pub fn segment(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let enter = self.boundary.hit(ray, f64::NEG_INFINITY, f64::INFINITY)?;
+        let exit = self
+            .boundary
+            .hit(ray, enter.t + BOUNDARY_EPSILON, f64::INFINITY)?;
+
+        let t_enter = enter.t.max(t_min);
+        let t_exit = exit.t.min(t_max);
+        if t_enter >= t_exit {
+            return None;
+        }
+        Some((t_enter.max(0.0), t_exit))
+    }
+
+    /// Sample where the ray scatters inside the medium, if it does so before
+    /// leaving the boundary or reaching `t_max`.
+    pub fn sample_distance<R: Rng>(
+        &self,
+        rng: &mut R,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<HitRecord> {
+        let (t_enter, t_exit) = self.segment(ray, t_min, t_max)?;
+
+        let ray_length = ray.direction.length();
+        let distance_inside = (t_exit - t_enter) * ray_length;
+        let hit_distance = -(1.0 - rng.gen::<f64>()).ln() / self.density;
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+        // the normal is arbitrary, point it back at the ray so the hit is
+        // always on the front face
+        let normal: Vec3 = ray.direction.scale(-1.0 / ray_length);
+        Some(HitRecord::new(ray, ray.at(t), normal, t, 0.0, 0.0))
+    }
+}
+
+impl Geometry for ConstantMedium {
+    fn hit(&self, _ray: &Ray, _t_min: f64, _t_max: f64) -> Option<HitRecord> {
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{
+        core::{Point, Vec3},
+        geometry::sphere::Sphere,
+    };
+
+    fn fog(density: f64) -> ConstantMedium {
+        ConstantMedium::new(
+            Sphere {
+                center: Point(Vec3::new(0.0, 0.0, -5.0)),
+                radius: 1.0,
+            },
+            density,
+        )
+    }
+
+    #[test]
+    fn segment_through_sphere() {
+        let r = Ray::new(Point(Vec3::default()), Vec3::new(0.0, 0.0, -1.0));
+        let (t0, t1) = fog(1.0).segment(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((t0 - 4.0).abs() < 1e-9);
+        assert!((t1 - 6.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn segment_from_inside() {
+        let r = Ray::new(Point(Vec3::new(0.0, 0.0, -5.0)), Vec3::new(0.0, 0.0, -2.0));
+        let (t0, t1) = fog(1.0).segment(&r, 0.001, f64::INFINITY).unwrap();
+        assert!((t0 - 0.001).abs() < 1e-9);
+        assert!((t1 - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn segment_clipped_by_surface() {
+        let r = Ray::new(Point(Vec3::default()), Vec3::new(0.0, 0.0, -1.0));
+        assert!(fog(1.0).segment(&r, 0.001, 3.0).is_none());
+    }
+
+    #[test]
+    fn dense_medium_scatters_inside() {
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(0xDEADBEEF);
+        let r = Ray::new(Point(Vec3::default()), Vec3::new(0.0, 0.0, -1.0));
+        let medium = fog(1000.0);
+        for _ in 0..100 {
+            let hit = medium
+                .sample_distance(&mut rng, &r, 0.001, f64::INFINITY)
+                .unwrap();
+            assert!(hit.t >= 4.0 && hit.t <= 6.0);
+            assert!(hit.front_face);
+        }
+    }
+
+    #[test]
+    fn thin_medium_mostly_passes() {
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(0xDEADBEEF);
+        let r = Ray::new(Point(Vec3::default()), Vec3::new(0.0, 0.0, -1.0));
+        let medium = fog(0.01);
+        let scattered = (0..1000)
+            .filter(|_| {
+                medium
+                    .sample_distance(&mut rng, &r, 0.001, f64::INFINITY)
+                    .is_some()
+            })
+            .count();
+        // expect 1 - e^(-0.02) ~= 2%
+        assert!(scattered > 5 && scattered < 50, "scattered {}", scattered);
+    }
+
+    #[test]
+    fn nested_media_are_rejected() {
+        use crate::{
+            geometry::{
+                csg::Csg,
+                transform::{Transform, Transformed},
+            },
+            material::{isotropic::Isotropic, texture::loader::TextureLoader},
+            scene::{PrototypeBuilder, SceneBuilder},
+        };
+        let smoke = || Isotropic::new(TextureLoader::solid(0.5, 0.5, 0.5));
+        let ball = || Sphere {
+            center: Point(Vec3::default()),
+            radius: 1.0,
+        };
+
+        let mut scene = SceneBuilder::default();
+        scene.add(fog(1.0), smoke());
+        assert!(scene.finalize().is_ok());
+
+        let mut scene = SceneBuilder::default();
+        scene.add(Transformed::new(fog(1.0), Transform::identity()), smoke());
+        assert!(scene.finalize().is_err());
+
+        let mut scene = SceneBuilder::default();
+        scene.add(Csg::union(ball(), fog(1.0)), smoke());
+        assert!(scene.finalize().is_err());
+
+        let mut scene = SceneBuilder::default();
+        scene.add(ConstantMedium::new(fog(1.0), 1.0), smoke());
+        assert!(scene.finalize().is_err());
+
+        let mut prototype = PrototypeBuilder::default();
+        prototype.add(fog(1.0), smoke());
+        let mut scene = SceneBuilder::default();
+        scene.add_prototype(prototype);
+        assert!(scene.finalize().is_err());
+    }
+}
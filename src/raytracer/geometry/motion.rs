@@ -0,0 +1,374 @@
+use std::convert::TryFrom;
+
+use nalgebra::UnitQuaternion;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    hittable::{Geometry, HitRecord},
+    object::GeometricObject,
+    sphere::Sphere,
+    transform::{rotation_quaternion, Transform},
+};
+use crate::{
+    bvh::{
+        aabb::{surrounding_box, Aabb},
+        bbox_tree::TraversalCounters,
+    },
+    core::{Point, Ray, Vec3},
+};
+
+/// How many poses are sampled to bound an animated transform.
+const MOTION_BOUND_STEPS: usize = 32;
+
+/// Fraction of the way through `[time0, time1]`, objects hold still outside
+/// of that interval.
+fn interval_fraction(time: f64, time0: f64, time1: f64) -> f64 {
+    if time1 <= time0 {
+        return 0.0;
+    }
+    ((time - time0) / (time1 - time0)).clamp(0.0, 1.0)
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct MovingSphere {
+    pub center0: Point,
+    pub center1: Point,
+    pub time0: f64,
+    pub time1: f64,
+    pub radius: f64,
+}
+
+impl MovingSphere {
+    pub fn center(&self, time: f64) -> Point {
+        let s = interval_fraction(time, self.time0, self.time1);
+        Point(self.center0.0 + (self.center1.0 - self.center0.0).scale(s))
+    }
+}
+
+impl Geometry for MovingSphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        Sphere {
+            center: self.center(ray.time),
+            radius: self.radius,
+        }
+        .hit(ray, t_min, t_max)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let r = Vec3::new(self.radius, self.radius, self.radius);
+        let b0 = Aabb {
+            min: Point(self.center0.0 - r),
+            max: Point(self.center0.0 + r),
+        };
+        let b1 = Aabb {
+            min: Point(self.center1.0 - r),
+            max: Point(self.center1.0 + r),
+        };
+        Some(surrounding_box(&b0, &b1))
+    }
+}
+
+fn default_scale() -> Vec3 {
+    Vec3::new(1.0, 1.0, 1.0)
+}
+
+fn default_axis() -> Vec3 {
+    Vec3::new(0.0, 1.0, 0.0)
+}
+
+/// One pose of an animated object, applied as scale, then rotate, then translate.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    #[serde(default)]
+    pub translate: Vec3,
+    #[serde(default = "default_axis")]
+    pub rotate_axis: Vec3,
+    #[serde(default)]
+    pub rotate_degrees: f64,
+    #[serde(default = "default_scale")]
+    pub scale: Vec3,
+}
+
+impl Default for Keyframe {
+    fn default() -> Self {
+        Keyframe {
+            translate: Vec3::default(),
+            rotate_axis: default_axis(),
+            rotate_degrees: 0.0,
+            scale: default_scale(),
+        }
+    }
+}
+
+impl Keyframe {
+    fn rotation(&self) -> UnitQuaternion<f64> {
+        rotation_quaternion(self.rotate_axis, self.rotate_degrees)
+    }
+}
+
+/// A transform that moves between two keyframes over `[time0, time1]`.
+///
+/// Translation and scale are interpolated linearly, rotation is slerped.
+#[derive(Serialize, Deserialize)]
+#[serde(try_from = "AnimatedFields")]
+pub struct Animated {
+    inner: Box<GeometricObject>,
+    start: Keyframe,
+    end: Keyframe,
+    time0: f64,
+    time1: f64,
+}
+
+/// The serialized form of `Animated`, checked before it is accepted.
+#[derive(Deserialize)]
+struct AnimatedFields {
+    inner: Box<GeometricObject>,
+    start: Keyframe,
+    end: Keyframe,
+    time0: f64,
+    time1: f64,
+}
+
+impl TryFrom<AnimatedFields> for Animated {
+    type Error = anyhow::Error;
+
+    fn try_from(f: AnimatedFields) -> Result<Self, Self::Error> {
+        Animated::new(*f.inner, f.start, f.end, f.time0, f.time1)
+    }
+}
+
+impl Animated {
+    /// Fails if a scale factor is zero at either keyframe, or changes sign
+    /// between them, as some pose in between could not be inverted.
+    pub fn new<G: Into<GeometricObject>>(
+        inner: G,
+        start: Keyframe,
+        end: Keyframe,
+        time0: f64,
+        time1: f64,
+    ) -> anyhow::Result<Animated> {
+        for d in 0..3 {
+            let (a, b) = (start.scale[d], end.scale[d]);
+            if !(a * b > 0.0 && a.is_finite() && b.is_finite()) {
+                anyhow::bail!(
+                    "keyframe scales {:?} and {:?} pass through zero, the pose can not be inverted",
+                    start.scale,
+                    end.scale
+                );
+            }
+        }
+        Ok(Animated {
+            inner: Box::new(inner.into()),
+            start,
+            end,
+            time0,
+            time1,
+        })
+    }
+
+    pub fn inner(&self) -> &GeometricObject {
+        &self.inner
+    }
+
+    fn pose(&self, s: f64) -> Transform {
+        let lerp = |a: Vec3, b: Vec3| a + (b - a).scale(s);
+        let rotation = self.start.rotation().slerp(&self.end.rotation(), s);
+        Transform::scale(lerp(self.start.scale, self.end.scale))
+            .then(&Transform::from_rotation(&rotation))
+            .then(&Transform::translate(lerp(
+                self.start.translate,
+                self.end.translate,
+            )))
+    }
+
+    pub fn transform_at(&self, time: f64) -> Transform {
+        self.pose(interval_fraction(time, self.time0, self.time1))
+    }
+}
+
+impl Geometry for Animated {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.hit_counted(ray, t_min, t_max, &mut None)
+    }
+
+    fn hit_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> Option<HitRecord> {
+        let transform = self.transform_at(ray.time);
+        let local = transform.inverse().ray(ray);
+        let mut record = self.inner.hit_counted(&local, t_min, t_max, counters)?;
+        record.point = transform.point(&record.point);
+        record.normal = transform.normal(&record.normal);
+        Some(record)
+    }
+
+    fn occluded_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> bool {
+        let local = self.transform_at(ray.time).inverse().ray(ray);
+        self.inner.occluded_counted(&local, t_min, t_max, counters)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let inner = self.inner.bounding_box()?;
+
+        // the corners sweep arcs between the sampled poses, pad by how far an
+        // arc can bow out from its chord
+        let step_angle =
+            self.start.rotation().angle_to(&self.end.rotation()) / MOTION_BOUND_STEPS as f64;
+        let max_scale = (0..3)
+            .map(|d| self.start.scale[d].abs().max(self.end.scale[d].abs()))
+            .fold(0.0, f64::max);
+        let reach = (0..8)
+            .map(|idx| {
+                let pick = |bit: usize, d: usize| {
+                    if idx & bit == 0 {
+                        inner.min.0[d]
+                    } else {
+                        inner.max.0[d]
+                    }
+                };
+                Vec3::new(pick(1, 0), pick(2, 1), pick(4, 2)).length()
+            })
+            .fold(0.0, f64::max)
+            * max_scale;
+        let pad = reach * (1.0 - (step_angle / 2.0).cos());
+        let pad = Vec3::new(pad, pad, pad);
+
+        let mut bbox: Option<Aabb> = None;
+        for step in 0..=MOTION_BOUND_STEPS {
+            let posed = self
+                .pose(step as f64 / MOTION_BOUND_STEPS as f64)
+                .bbox(&inner);
+            bbox = Some(match bbox {
+                Some(b) => surrounding_box(&b, &posed),
+                None => posed,
+            });
+        }
+        bbox.map(|b| Aabb {
+            min: Point(b.min.0 - pad),
+            max: Point(b.max.0 + pad),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moving() -> MovingSphere {
+        MovingSphere {
+            center0: Point(Vec3::new(0.0, 0.0, -5.0)),
+            center1: Point(Vec3::new(0.0, 2.0, -5.0)),
+            time0: 0.0,
+            time1: 1.0,
+            radius: 0.5,
+        }
+    }
+
+    #[test]
+    fn moving_sphere_follows_time() {
+        let s = moving();
+        let dir = Vec3::new(0.0, 0.0, -1.0);
+        let start = Ray::with_time(Point(Vec3::default()), dir, 0.0);
+        let end = Ray::with_time(Point(Vec3::default()), dir, 1.0);
+        assert!(s.hit(&start, 0.0, f64::MAX).is_some());
+        assert!(s.hit(&end, 0.0, f64::MAX).is_none());
+
+        let raised = Ray::with_time(Point(Vec3::new(0.0, 2.0, 0.0)), dir, 1.0);
+        assert!(s.hit(&raised, 0.0, f64::MAX).is_some());
+    }
+
+    #[test]
+    fn moving_sphere_holds_outside_interval() {
+        let s = moving();
+        assert_eq!(s.center(-1.0), s.center0);
+        assert_eq!(s.center(4.0), s.center1);
+    }
+
+    #[test]
+    fn moving_sphere_bounds_whole_path() {
+        let bbox = moving().bounding_box().unwrap();
+        assert_eq!(bbox.min, Point(Vec3::new(-0.5, -0.5, -5.5)));
+        assert_eq!(bbox.max, Point(Vec3::new(0.5, 2.5, -4.5)));
+    }
+
+    #[test]
+    fn animated_bounds_contain_every_pose() {
+        let unit = Sphere {
+            center: Point(Vec3::new(2.0, 0.0, 0.0)),
+            radius: 0.5,
+        };
+        let start = Keyframe::default();
+        let end = Keyframe {
+            translate: Vec3::new(0.0, 3.0, 0.0),
+            rotate_degrees: 180.0,
+            ..Keyframe::default()
+        };
+        let animated = Animated::new(unit.clone(), start, end, 0.0, 1.0).unwrap();
+        let bbox = animated.bounding_box().unwrap();
+        for step in 0..=100 {
+            let time = step as f64 / 100.0;
+            let posed = animated
+                .transform_at(time)
+                .bbox(&unit.bounding_box().unwrap());
+            assert_eq!(surrounding_box(&bbox, &posed), bbox, "time {}", time);
+        }
+    }
+
+    #[test]
+    fn animated_hit_uses_ray_time() {
+        let unit = Sphere {
+            center: Point(Vec3::default()),
+            radius: 0.5,
+        };
+        let end = Keyframe {
+            translate: Vec3::new(3.0, 0.0, 0.0),
+            ..Keyframe::default()
+        };
+        let animated = Animated::new(unit, Keyframe::default(), end, 0.0, 1.0).unwrap();
+        let orig = Point(Vec3::new(3.0, 0.0, 5.0));
+        let dir = Vec3::new(0.0, 0.0, -1.0);
+        assert!(animated
+            .hit(&Ray::with_time(orig, dir, 0.0), 0.0, f64::MAX)
+            .is_none());
+        let hit = animated
+            .hit(&Ray::with_time(orig, dir, 1.0), 0.0, f64::MAX)
+            .unwrap();
+        assert!((hit.t - 4.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn reject_scale_through_zero() {
+        let unit = || Sphere {
+            center: Point(Vec3::default()),
+            radius: 0.5,
+        };
+        let flat = Keyframe {
+            scale: Vec3::new(1.0, 0.0, 1.0),
+            ..Keyframe::default()
+        };
+        assert!(Animated::new(unit(), flat, Keyframe::default(), 0.0, 1.0).is_err());
+        let mirrored = Keyframe {
+            scale: Vec3::new(-1.0, 1.0, 1.0),
+            ..Keyframe::default()
+        };
+        assert!(Animated::new(unit(), Keyframe::default(), mirrored, 0.0, 1.0).is_err());
+        assert!(Animated::new(unit(), mirrored, mirrored, 0.0, 1.0).is_ok());
+
+        // the same check runs when a scene is loaded
+        let animated = Animated::new(unit(), Keyframe::default(), Keyframe::default(), 0.0, 1.0);
+        let mut json = serde_json::to_value(animated.unwrap()).unwrap();
+        assert!(serde_json::from_value::<Animated>(json.clone()).is_ok());
+        json["end"]["scale"] = serde_json::to_value(Vec3::new(-1.0, 1.0, 1.0)).unwrap();
+        assert!(serde_json::from_value::<Animated>(json).is_err());
+    }
+}
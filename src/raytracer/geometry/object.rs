@@ -1,9 +1,23 @@
 use serde::{Deserialize, Serialize};
 
+use crate::bvh::bbox_tree::TraversalCounters;
+
 use super::{
+    csg::Csg,
+    cylinder::{Cone, Cylinder},
+    disk::Disk,
+    heightfield::Heightfield,
     hittable::Geometry,
+    medium::ConstantMedium,
+    motion::{Animated, MovingSphere},
+    plane::Plane,
+    quad::{Quad, QuadBox},
     rect::{RectBox, RectXY, RectXZ, RectYZ},
+    sdf::Sdf,
     sphere::Sphere,
+    torus::Torus,
+    transform::Transformed,
+    triangle::{Triangle, TriangleMesh},
 };
 
 #[derive(Serialize, Deserialize)]
@@ -12,7 +26,23 @@ pub enum GeometricObject {
     RectXY(RectXY),
     RectYZ(RectYZ),
     RectXZ(RectXZ),
-    RectBox(RectBox),
+    RectBox(Box<RectBox>),
+    Triangle(Triangle),
+    TriangleMesh(TriangleMesh),
+    Transformed(Box<Transformed>),
+    ConstantMedium(ConstantMedium),
+    MovingSphere(MovingSphere),
+    Animated(Animated),
+    Quad(Quad),
+    QuadBox(Box<QuadBox>),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Disk(Disk),
+    Torus(Torus),
+    Csg(Csg),
+    Sdf(Sdf),
+    Heightfield(Heightfield),
+    Plane(Plane),
 }
 
 impl From<Sphere> for GeometricObject {
@@ -37,7 +67,140 @@ impl From<RectXZ> for GeometricObject {
 }
 impl From<RectBox> for GeometricObject {
     fn from(s: RectBox) -> Self {
-        GeometricObject::RectBox(s)
+        GeometricObject::RectBox(Box::new(s))
+    }
+}
+
+impl From<Triangle> for GeometricObject {
+    fn from(s: Triangle) -> Self {
+        GeometricObject::Triangle(s)
+    }
+}
+impl From<TriangleMesh> for GeometricObject {
+    fn from(s: TriangleMesh) -> Self {
+        GeometricObject::TriangleMesh(s)
+    }
+}
+
+impl From<Transformed> for GeometricObject {
+    fn from(s: Transformed) -> Self {
+        GeometricObject::Transformed(Box::new(s))
+    }
+}
+
+impl From<ConstantMedium> for GeometricObject {
+    fn from(s: ConstantMedium) -> Self {
+        GeometricObject::ConstantMedium(s)
+    }
+}
+
+impl From<MovingSphere> for GeometricObject {
+    fn from(s: MovingSphere) -> Self {
+        GeometricObject::MovingSphere(s)
+    }
+}
+impl From<Animated> for GeometricObject {
+    fn from(s: Animated) -> Self {
+        GeometricObject::Animated(s)
+    }
+}
+
+impl From<Quad> for GeometricObject {
+    fn from(s: Quad) -> Self {
+        GeometricObject::Quad(s)
+    }
+}
+impl From<QuadBox> for GeometricObject {
+    fn from(s: QuadBox) -> Self {
+        GeometricObject::QuadBox(Box::new(s))
+    }
+}
+impl From<Cylinder> for GeometricObject {
+    fn from(s: Cylinder) -> Self {
+        GeometricObject::Cylinder(s)
+    }
+}
+impl From<Cone> for GeometricObject {
+    fn from(s: Cone) -> Self {
+        GeometricObject::Cone(s)
+    }
+}
+impl From<Disk> for GeometricObject {
+    fn from(s: Disk) -> Self {
+        GeometricObject::Disk(s)
+    }
+}
+impl From<Torus> for GeometricObject {
+    fn from(s: Torus) -> Self {
+        GeometricObject::Torus(s)
+    }
+}
+
+impl From<Csg> for GeometricObject {
+    fn from(s: Csg) -> Self {
+        GeometricObject::Csg(s)
+    }
+}
+
+impl From<Sdf> for GeometricObject {
+    fn from(s: Sdf) -> Self {
+        GeometricObject::Sdf(s)
+    }
+}
+
+impl From<Heightfield> for GeometricObject {
+    fn from(s: Heightfield) -> Self {
+        GeometricObject::Heightfield(s)
+    }
+}
+
+impl From<Plane> for GeometricObject {
+    fn from(s: Plane) -> Self {
+        GeometricObject::Plane(s)
+    }
+}
+
+impl GeometricObject {
+    /// Whether this is, or wraps, a `ConstantMedium`. Media are only sampled
+    /// when they sit at the top level of a scene.
+    pub fn contains_medium(&self) -> bool {
+        match self {
+            GeometricObject::ConstantMedium(_) => true,
+            GeometricObject::Transformed(x) => x.inner().contains_medium(),
+            GeometricObject::Animated(x) => x.inner().contains_medium(),
+            GeometricObject::Csg(x) => {
+                let (left, right) = x.children();
+                left.contains_medium() || right.contains_medium()
+            }
+            _ => false,
+        }
+    }
+
+    /// The name of the variant, for messages about a particular object.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            GeometricObject::Sphere(_) => "Sphere",
+            GeometricObject::RectXY(_) => "RectXY",
+            GeometricObject::RectYZ(_) => "RectYZ",
+            GeometricObject::RectXZ(_) => "RectXZ",
+            GeometricObject::RectBox(_) => "RectBox",
+            GeometricObject::Triangle(_) => "Triangle",
+            GeometricObject::TriangleMesh(_) => "TriangleMesh",
+            GeometricObject::Transformed(_) => "Transformed",
+            GeometricObject::ConstantMedium(_) => "ConstantMedium",
+            GeometricObject::MovingSphere(_) => "MovingSphere",
+            GeometricObject::Animated(_) => "Animated",
+            GeometricObject::Quad(_) => "Quad",
+            GeometricObject::QuadBox(_) => "QuadBox",
+            GeometricObject::Cylinder(_) => "Cylinder",
+            GeometricObject::Cone(_) => "Cone",
+            GeometricObject::Disk(_) => "Disk",
+            GeometricObject::Torus(_) => "Torus",
+            GeometricObject::Csg(_) => "Csg",
+            GeometricObject::Sdf(_) => "Sdf",
+            GeometricObject::Heightfield(_) => "Heightfield",
+            GeometricObject::Plane(_) => "Plane",
+        }
     }
 }
 
@@ -48,13 +211,7 @@ impl Geometry for GeometricObject {
         t_min: f64,
         t_max: f64,
     ) -> Option<super::hittable::HitRecord> {
-        match self {
-            GeometricObject::Sphere(x) => x.hit(ray, t_min, t_max),
-            GeometricObject::RectXY(x) => x.hit(ray, t_min, t_max),
-            GeometricObject::RectYZ(x) => x.hit(ray, t_min, t_max),
-            GeometricObject::RectXZ(x) => x.hit(ray, t_min, t_max),
-            GeometricObject::RectBox(x) => x.hit(ray, t_min, t_max),
-        }
+        self.hit_counted(ray, t_min, t_max, &mut None)
     }
 
     fn bounding_box(&self) -> Option<crate::bvh::aabb::Aabb> {
@@ -64,6 +221,90 @@ impl Geometry for GeometricObject {
             GeometricObject::RectYZ(x) => x.bounding_box(),
             GeometricObject::RectXZ(x) => x.bounding_box(),
             GeometricObject::RectBox(x) => x.bounding_box(),
+            GeometricObject::Triangle(x) => x.bounding_box(),
+            GeometricObject::TriangleMesh(x) => x.bounding_box(),
+            GeometricObject::Transformed(x) => x.bounding_box(),
+            GeometricObject::ConstantMedium(x) => x.bounding_box(),
+            GeometricObject::MovingSphere(x) => x.bounding_box(),
+            GeometricObject::Animated(x) => x.bounding_box(),
+            GeometricObject::Quad(x) => x.bounding_box(),
+            GeometricObject::QuadBox(x) => x.bounding_box(),
+            GeometricObject::Cylinder(x) => x.bounding_box(),
+            GeometricObject::Cone(x) => x.bounding_box(),
+            GeometricObject::Disk(x) => x.bounding_box(),
+            GeometricObject::Torus(x) => x.bounding_box(),
+            GeometricObject::Csg(x) => x.bounding_box(),
+            GeometricObject::Sdf(x) => x.bounding_box(),
+            GeometricObject::Heightfield(x) => x.bounding_box(),
+            GeometricObject::Plane(x) => x.bounding_box(),
+        }
+    }
+
+    fn occluded(&self, ray: &crate::core::Ray, t_min: f64, t_max: f64) -> bool {
+        self.occluded_counted(ray, t_min, t_max, &mut None)
+    }
+
+    fn hit_counted(
+        &self,
+        ray: &crate::core::Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> Option<super::hittable::HitRecord> {
+        match self {
+            GeometricObject::Sphere(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::RectXY(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::RectYZ(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::RectXZ(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::RectBox(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::Triangle(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::TriangleMesh(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::Transformed(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::ConstantMedium(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::MovingSphere(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::Animated(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::Quad(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::QuadBox(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::Cylinder(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::Cone(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::Disk(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::Torus(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::Csg(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::Sdf(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::Heightfield(x) => x.hit_counted(ray, t_min, t_max, counters),
+            GeometricObject::Plane(x) => x.hit_counted(ray, t_min, t_max, counters),
+        }
+    }
+
+    fn occluded_counted(
+        &self,
+        ray: &crate::core::Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> bool {
+        match self {
+            GeometricObject::Sphere(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::RectXY(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::RectYZ(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::RectXZ(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::RectBox(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::Triangle(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::TriangleMesh(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::Transformed(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::ConstantMedium(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::MovingSphere(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::Animated(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::Quad(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::QuadBox(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::Cylinder(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::Cone(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::Disk(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::Torus(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::Csg(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::Sdf(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::Heightfield(x) => x.occluded_counted(ray, t_min, t_max, counters),
+            GeometricObject::Plane(x) => x.occluded_counted(ray, t_min, t_max, counters),
         }
     }
 }
@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+
+use super::hittable::{Geometry, HitRecord};
+use crate::{
+    bvh::aabb::Aabb,
+    core::{math::from_local_frame, Point, Ray, Vec3},
+};
+
+const PARALLEL_EPSILON: f64 = 1e-12;
+
+fn default_tile_size() -> f64 {
+    1.0
+}
+
+/// An infinite plane through `point` facing `normal`.
+///
+/// It has no bounding box, so a scene keeps it out of the BVH and tests it
+/// against every ray.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Plane {
+    pub point: Point,
+    pub normal: Vec3,
+    /// Distance over which the uvs go from 0 to 1 before repeating
+    #[serde(default = "default_tile_size")]
+    pub tile_size: f64,
+}
+
+impl Plane {
+    pub fn new(point: Point, normal: Vec3) -> Plane {
+        Plane {
+            point,
+            normal: normal.unit(),
+            tile_size: default_tile_size(),
+        }
+    }
+
+    pub fn with_tile_size(mut self, tile_size: f64) -> Plane {
+        self.tile_size = tile_size;
+        self
+    }
+}
+
+impl Geometry for Plane {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let n = self.normal.unit();
+        let denom = n.dot(&ray.direction);
+        if denom.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+        let t = n.dot(&(self.point.0 - ray.orig.0)) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+        let point = ray.at(t);
+
+        // planar coordinates in the frame around the normal, wrapped to
+        // repeat every tile
+        let offset = point.0 - self.point.0;
+        let tangent = from_local_frame(&n, &Vec3::new(1.0, 0.0, 0.0));
+        let bitangent = from_local_frame(&n, &Vec3::new(0.0, 1.0, 0.0));
+        let u = (offset.dot(&tangent) / self.tile_size).rem_euclid(1.0);
+        let v = (offset.dot(&bitangent) / self.tile_size).rem_euclid(1.0);
+        Some(HitRecord::new(ray, point, n, t, u, v))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hit_ground_plane() {
+        let plane = Plane::new(Point(Vec3::new(0.0, -1.0, 0.0)), Vec3::new(0.0, 2.0, 0.0));
+        let r = Ray::new(Point(Vec3::new(0.0, 1.0, 0.0)), Vec3::new(0.0, -1.0, 1.0));
+        let hit = plane.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-9);
+        assert!(hit.front_face);
+        assert!((hit.normal - Vec3::new(0.0, 1.0, 0.0)).near_zero());
+
+        // from below it is the back face
+        let r = Ray::new(Point(Vec3::new(0.0, -3.0, 0.0)), Vec3::new(0.0, 1.0, 0.0));
+        let hit = plane.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!(!hit.front_face);
+
+        // parallel and pointing away both miss
+        let r = Ray::new(Point(Vec3::new(0.0, 1.0, 0.0)), Vec3::new(1.0, 0.0, 0.0));
+        assert!(plane.hit(&r, 0.0, f64::MAX).is_none());
+        let r = Ray::new(Point(Vec3::new(0.0, 1.0, 0.0)), Vec3::new(0.0, 1.0, 0.0));
+        assert!(plane.hit(&r, 0.0, f64::MAX).is_none());
+        assert!(plane.bounding_box().is_none());
+    }
+
+    #[test]
+    fn uvs_tile() {
+        let plane =
+            Plane::new(Point(Vec3::default()), Vec3::new(0.0, 1.0, 0.0)).with_tile_size(2.0);
+        let uv_at = |x: f64, z: f64| {
+            let r = Ray::new(Point(Vec3::new(x, 1.0, z)), Vec3::new(0.0, -1.0, 0.0));
+            let hit = plane.hit(&r, 0.0, f64::MAX).unwrap();
+            (hit.u, hit.v)
+        };
+        let (u, v) = uv_at(0.5, -0.25);
+        assert!((0.0..1.0).contains(&u) && (0.0..1.0).contains(&v));
+        // one tile over, in either direction, lands on the same uv
+        for (dx, dz) in [(2.0, 0.0), (0.0, -2.0), (-4.0, 6.0)] {
+            let (u2, v2) = uv_at(0.5 + dx, -0.25 + dz);
+            assert!((u - u2).abs() < 1e-9 && (v - v2).abs() < 1e-9);
+        }
+        // half a tile over doesn't
+        let (u2, v2) = uv_at(1.5, -0.25);
+        assert!((u - u2).abs() > 0.1 || (v - v2).abs() > 0.1);
+    }
+}
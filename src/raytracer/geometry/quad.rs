@@ -0,0 +1,263 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::hittable::{Geometry, HitRecord, SolidAngleSampler};
+use crate::{
+    bvh::aabb::{bounding, Aabb},
+    core::{Point, Ray, Vec3},
+};
+
+const BBOX_WIDTH: f64 = 0.0001;
+const PARALLEL_EPSILON: f64 = 1e-12;
+
+/// A parallelogram with a corner at `q`, spanned by the edges `u` and `v`.
+///
+/// The front face is the side `u x v` points to, and `(u, v)` on a hit are
+/// the coordinates along the two edges.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Quad {
+    pub q: Point,
+    pub u: Vec3,
+    pub v: Vec3,
+}
+
+impl Quad {
+    pub fn new(q: Point, u: Vec3, v: Vec3) -> Quad {
+        Quad { q, u, v }
+    }
+
+    pub fn area(&self) -> f64 {
+        self.u.cross(&self.v).length()
+    }
+
+    pub fn corners(&self) -> [Point; 4] {
+        let q = self.q.0;
+        [
+            Point(q),
+            Point(q + self.u),
+            Point(q + self.v),
+            Point(q + self.u + self.v),
+        ]
+    }
+}
+
+impl Geometry for Quad {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let n = self.u.cross(&self.v);
+        let denom = n.dot(&ray.direction);
+        if denom.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+        let t = n.dot(&(self.q.0 - ray.orig.0)) / denom;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        // planar coordinates of the hit along the two edges
+        let point = ray.at(t);
+        let p = point.0 - self.q.0;
+        let w = n.scale(1.0 / n.length_squared());
+        let alpha = w.dot(&p.cross(&self.v));
+        let beta = w.dot(&self.u.cross(&p));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        Some(HitRecord::new(ray, point, n.unit(), t, alpha, beta))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let corners = self
+            .corners()
+            .iter()
+            .map(|c| Aabb { min: *c, max: *c })
+            .collect::<Vec<_>>();
+        let mut b = bounding(corners.iter())?;
+        for d in 0..3 {
+            if b.max.0[d] - b.min.0[d] < BBOX_WIDTH {
+                b.min.0[d] -= BBOX_WIDTH;
+                b.max.0[d] += BBOX_WIDTH;
+            }
+        }
+        Some(b)
+    }
+}
+
+impl SolidAngleSampler for Quad {
+    fn sample_direction<R: Rng>(&self, rng: &mut R, origin: &Point) -> Vec3 {
+        let target = self.q.0 + self.u.scale(rng.gen::<f64>()) + self.v.scale(rng.gen::<f64>());
+        target - origin.0
+    }
+
+    fn direction_pdf(&self, origin: &Point, direction: &Vec3) -> f64 {
+        let ray = Ray::new(*origin, *direction);
+        match self.hit(&ray, 0.001, f64::INFINITY) {
+            Some(hit) => {
+                let distance_squared = hit.t * hit.t * direction.length_squared();
+                let cosine = (direction.dot(&hit.normal) / direction.length()).abs();
+                distance_squared / (cosine * self.area())
+            }
+            None => 0.0,
+        }
+    }
+}
+
+/// A parallelepiped made of six quads, with a corner at `origin` and the
+/// three edges `a`, `b` and `c`. Unlike `RectBox` it can face any direction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QuadBox {
+    sides: [Quad; 6],
+}
+
+impl QuadBox {
+    pub fn new(origin: Point, a: Vec3, b: Vec3, c: Vec3) -> QuadBox {
+        let o = origin.0;
+        let far = Point(o + a + b + c);
+        // orient every side so the front face points out of the box
+        let (a, b, c) = if a.cross(&b).dot(&c) < 0.0 {
+            (b, a, c)
+        } else {
+            (a, b, c)
+        };
+        QuadBox {
+            sides: [
+                Quad::new(origin, b, a),
+                Quad::new(origin, c, b),
+                Quad::new(origin, a, c),
+                Quad::new(far, a.scale(-1.0), b.scale(-1.0)),
+                Quad::new(far, b.scale(-1.0), c.scale(-1.0)),
+                Quad::new(far, c.scale(-1.0), a.scale(-1.0)),
+            ],
+        }
+    }
+
+    /// An axis aligned box between two opposite corners.
+    pub fn from_corners(p0: Point, p1: Point) -> QuadBox {
+        let min = Vec3::new(
+            p0.0.x().min(p1.0.x()),
+            p0.0.y().min(p1.0.y()),
+            p0.0.z().min(p1.0.z()),
+        );
+        let size = Vec3::new(
+            (p0.0.x() - p1.0.x()).abs(),
+            (p0.0.y() - p1.0.y()).abs(),
+            (p0.0.z() - p1.0.z()).abs(),
+        );
+        QuadBox::new(
+            Point(min),
+            Vec3::new(size.x(), 0.0, 0.0),
+            Vec3::new(0.0, size.y(), 0.0),
+            Vec3::new(0.0, 0.0, size.z()),
+        )
+    }
+
+    pub fn sides(&self) -> &[Quad] {
+        &self.sides
+    }
+}
+
+impl Geometry for QuadBox {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let mut closest: Option<HitRecord> = None;
+        for side in &self.sides {
+            let t_closest = closest.as_ref().map(|r| r.t).unwrap_or(t_max);
+            if let Some(hit) = side.hit(ray, t_min, t_closest) {
+                closest = Some(hit);
+            }
+        }
+        closest
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let boxes = self
+            .sides
+            .iter()
+            .filter_map(|s| s.bounding_box())
+            .collect::<Vec<_>>();
+        bounding(boxes.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tilted() -> Quad {
+        Quad::new(
+            Point(Vec3::new(-1.0, -1.0, -3.0)),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 2.0),
+        )
+    }
+
+    #[test]
+    fn hit_tilted_quad() {
+        let r = Ray::new(Point(Vec3::default()), Vec3::new(0.0, 0.0, -1.0));
+        let hit = tilted().hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-9);
+        assert!((hit.u - 0.5).abs() < 1e-9);
+        assert!((hit.v - 0.5).abs() < 1e-9);
+        assert!(hit.front_face);
+        let expected = Vec3::new(0.0, -1.0, 1.0).unit();
+        assert!((hit.normal - expected).near_zero());
+
+        let r = Ray::new(Point(Vec3::new(1.5, 0.0, 0.0)), Vec3::new(0.0, 0.0, -1.0));
+        assert!(tilted().hit(&r, 0.0, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn flat_quad_has_volume() {
+        let q = Quad::new(
+            Point(Vec3::default()),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        let b = q.bounding_box().unwrap();
+        assert!(b.max.0.y() > b.min.0.y());
+    }
+
+    #[test]
+    fn box_faces_point_out() {
+        // a left handed set of edges still gives outward faces
+        let b = QuadBox::new(
+            Point(Vec3::default()),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        let center = Vec3::new(0.5, 0.5, 0.5);
+        for side in b.sides() {
+            let mid = side.q.0 + side.u.scale(0.5) + side.v.scale(0.5);
+            assert!(side.u.cross(&side.v).dot(&(mid - center)) > 0.0);
+        }
+    }
+
+    #[test]
+    fn rotated_box() {
+        let s = std::f64::consts::FRAC_1_SQRT_2;
+        let b = QuadBox::new(
+            Point(Vec3::default()),
+            Vec3::new(s, s, 0.0),
+            Vec3::new(-s, s, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        let bbox = b.bounding_box().unwrap();
+        assert!((bbox.min.0.x() + s).abs() < 1e-9);
+        assert!((bbox.max.0.y() - 2.0 * s).abs() < 1e-9);
+
+        let r = Ray::new(Point(Vec3::new(0.1, 5.0, 0.5)), Vec3::new(0.0, -1.0, 0.0));
+        let hit = b.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - (5.0 - (2.0 * s - 0.1))).abs() < 1e-9);
+        assert!(hit.front_face);
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let b = QuadBox::from_corners(
+            Point(Vec3::new(1.0, 2.0, 3.0)),
+            Point(Vec3::new(-1.0, 0.0, 0.0)),
+        );
+        let json = serde_json::to_string(&b).unwrap();
+        let loaded: QuadBox = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.bounding_box(), b.bounding_box());
+    }
+}
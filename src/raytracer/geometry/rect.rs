@@ -1,9 +1,10 @@
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
-use super::hittable::{Geometry, HitRecord};
+use super::hittable::{Geometry, HitRecord, SolidAngleSampler};
 use crate::{
     bvh::aabb::Aabb,
-    core::{Point, Ray, Vec3},
+    core::{math::random_real, Point, Ray, Vec3},
 };
 
 const BBOX_WIDTH: f64 = 0.0001;
@@ -42,7 +43,7 @@ pub fn xz_rect(d1_min: f64, d1_max: f64, d2_min: f64, d2_max: f64, offset: f64)
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Rect<const D1: usize, const D2: usize> {
     d1_min: f64,
     d1_max: f64,
@@ -99,6 +100,35 @@ impl<const D1: usize, const D2: usize> Geometry for Rect<D1, D2> {
     }
 }
 
+impl<const D1: usize, const D2: usize> Rect<D1, D2> {
+    pub fn area(&self) -> f64 {
+        (self.d1_max - self.d1_min) * (self.d2_max - self.d2_min)
+    }
+}
+
+impl<const D1: usize, const D2: usize> SolidAngleSampler for Rect<D1, D2> {
+    fn sample_direction<R: Rng>(&self, rng: &mut R, origin: &Point) -> Vec3 {
+        let dimm_normal = 3 - D1 - D2;
+        let mut target = Vec3::default();
+        target[D1] = random_real(rng, self.d1_min, self.d1_max);
+        target[D2] = random_real(rng, self.d2_min, self.d2_max);
+        target[dimm_normal] = self.offset;
+        target - origin.0
+    }
+
+    fn direction_pdf(&self, origin: &Point, direction: &Vec3) -> f64 {
+        let ray = Ray::new(*origin, *direction);
+        match self.hit(&ray, 0.001, f64::INFINITY) {
+            Some(hit) => {
+                let distance_squared = hit.t * hit.t * direction.length_squared();
+                let cosine = (direction.dot(&hit.normal) / direction.length()).abs();
+                distance_squared / (cosine * self.area())
+            }
+            None => 0.0,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct RectBox {
     min: Point,
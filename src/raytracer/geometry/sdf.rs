@@ -0,0 +1,289 @@
+use serde::{Deserialize, Serialize};
+
+use super::hittable::{Geometry, HitRecord};
+use crate::{
+    bvh::aabb::Aabb,
+    core::{Point, Ray, Vec3},
+};
+
+const MAX_STEPS: usize = 512;
+/// Closer than this to the surface counts as a hit.
+const HIT_EPSILON: f64 = 1e-5;
+/// Offset for the central differences of the normal.
+const GRADIENT_EPSILON: f64 = 1e-6;
+
+fn default_step_scale() -> f64 {
+    1.0
+}
+
+/// A small expression tree describing a signed distance field, negative
+/// inside the surface.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SdfNode {
+    Sphere {
+        center: Point,
+        radius: f64,
+    },
+    Cuboid {
+        center: Point,
+        half_size: Vec3,
+        /// Round the edges off, this grows the box by the same amount
+        #[serde(default)]
+        rounding: f64,
+    },
+    /// A ring around the y axis, like `Torus`
+    Torus {
+        center: Point,
+        major_radius: f64,
+        minor_radius: f64,
+    },
+    /// Union that blends the surfaces together within `smoothness`, a plain
+    /// union when it is zero
+    SmoothUnion {
+        left: Box<SdfNode>,
+        right: Box<SdfNode>,
+        smoothness: f64,
+    },
+    /// Tile space every `period`, an axis with a zero period isn't repeated
+    Repeat {
+        period: Vec3,
+        node: Box<SdfNode>,
+    },
+    /// Rotate around the y axis by `rate` radians per unit of height
+    Twist {
+        rate: f64,
+        node: Box<SdfNode>,
+    },
+}
+
+impl SdfNode {
+    pub fn distance(&self, p: &Vec3) -> f64 {
+        match self {
+            SdfNode::Sphere { center, radius } => (*p - center.0).length() - radius,
+            SdfNode::Cuboid {
+                center,
+                half_size,
+                rounding,
+            } => {
+                let q = (*p - center.0).map(f64::abs) - *half_size;
+                let outside = q.map(|x| x.max(0.0)).length();
+                let inside = q.x().max(q.y()).max(q.z()).min(0.0);
+                outside + inside - rounding
+            }
+            SdfNode::Torus {
+                center,
+                major_radius,
+                minor_radius,
+            } => {
+                let q = *p - center.0;
+                let ring = (q.x() * q.x() + q.z() * q.z()).sqrt() - major_radius;
+                (ring * ring + q.y() * q.y()).sqrt() - minor_radius
+            }
+            SdfNode::SmoothUnion {
+                left,
+                right,
+                smoothness,
+            } => {
+                let a = left.distance(p);
+                let b = right.distance(p);
+                if *smoothness <= 0.0 {
+                    return a.min(b);
+                }
+                let h = (0.5 + 0.5 * (b - a) / smoothness).clamp(0.0, 1.0);
+                b + (a - b) * h - smoothness * h * (1.0 - h)
+            }
+            SdfNode::Repeat { period, node } => {
+                let mut q = *p;
+                for d in 0..3 {
+                    if period[d] > 0.0 {
+                        q[d] -= period[d] * (q[d] / period[d]).round();
+                    }
+                }
+                node.distance(&q)
+            }
+            SdfNode::Twist { rate, node } => {
+                let (s, c) = (rate * p.y()).sin_cos();
+                let q = Vec3::new(c * p.x() - s * p.z(), p.y(), s * p.x() + c * p.z());
+                node.distance(&q)
+            }
+        }
+    }
+
+    fn gradient(&self, p: &Vec3) -> Vec3 {
+        let mut grad = Vec3::default();
+        for d in 0..3 {
+            let mut offset = Vec3::default();
+            offset[d] = GRADIENT_EPSILON;
+            grad[d] = self.distance(&(*p + offset)) - self.distance(&(*p - offset));
+        }
+        grad
+    }
+}
+
+/// Geometry defined by a distance field, intersected by sphere tracing.
+///
+/// The field can't tell how big it is, so the caller supplies a box it fits
+/// in, which also keeps the march short.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Sdf {
+    pub root: SdfNode,
+    pub bound_min: Point,
+    pub bound_max: Point,
+    /// Fraction of the distance to advance each step, lower it for fields
+    /// that overestimate the distance like `Twist`
+    #[serde(default = "default_step_scale")]
+    pub step_scale: f64,
+}
+
+impl Sdf {
+    pub fn new(root: SdfNode, bounds: Aabb) -> Sdf {
+        Sdf {
+            root,
+            bound_min: bounds.min,
+            bound_max: bounds.max,
+            step_scale: default_step_scale(),
+        }
+    }
+}
+
+impl Geometry for Sdf {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (start, end) = self.bounding_box()?.clip(ray, t_min, t_max)?;
+        let length = ray.direction.length();
+
+        // a ray entering the bounds starts outside the surface, otherwise
+        // march on whichever side it starts, and when it starts right on the
+        // surface the direction decides
+        let first = self.root.distance(&ray.at(start).0);
+        let side = if start > t_min {
+            1.0
+        } else if first.abs() > HIT_EPSILON {
+            first.signum()
+        } else {
+            self.root
+                .gradient(&ray.at(start).0)
+                .dot(&ray.direction)
+                .signum()
+        };
+
+        // a ray leaving the surface shouldn't hit it again straight away
+        let mut left_surface = start > t_min;
+        let mut t = start;
+        for _ in 0..MAX_STEPS {
+            if t > end {
+                return None;
+            }
+            let point = ray.at(t);
+            let distance = side * self.root.distance(&point.0);
+            if left_surface && distance < HIT_EPSILON {
+                let normal = self.root.gradient(&point.0).unit();
+                return Some(HitRecord::new(ray, point, normal, t, 0.0, 0.0));
+            }
+            left_surface |= distance >= HIT_EPSILON;
+            t += self.step_scale * distance.max(HIT_EPSILON) / length;
+        }
+        None
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        Some(Aabb {
+            min: self.bound_min,
+            max: self.bound_max,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds(size: f64) -> Aabb {
+        Aabb {
+            min: Point(Vec3::new(-size, -size, -size)),
+            max: Point(Vec3::new(size, size, size)),
+        }
+    }
+
+    fn unit_sphere() -> SdfNode {
+        SdfNode::Sphere {
+            center: Point(Vec3::default()),
+            radius: 1.0,
+        }
+    }
+
+    #[test]
+    fn sphere_trace_sphere() {
+        let sdf = Sdf::new(unit_sphere(), bounds(1.0));
+        let r = Ray::new(Point(Vec3::new(0.0, 0.0, 5.0)), Vec3::new(0.0, 0.0, -2.0));
+        let hit = sdf.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-4);
+        assert!(hit.front_face);
+        assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).near_zero());
+
+        // and out the other side, as a refracted ray would
+        let hit = sdf.hit(&r, hit.t + 0.001, f64::MAX).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-4);
+        assert!(!hit.front_face);
+
+        let r = Ray::new(Point(Vec3::new(0.0, 1.1, 5.0)), Vec3::new(0.0, 0.0, -1.0));
+        assert!(sdf.hit(&r, 0.0, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn leaving_the_surface() {
+        let sdf = Sdf::new(unit_sphere(), bounds(1.0));
+        let r = Ray::new(Point(Vec3::new(0.0, 0.0, 1.0)), Vec3::new(0.0, 1.0, 1.0));
+        assert!(sdf.hit(&r, 0.0, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn smooth_union_fills_the_gap() {
+        let pair = |smoothness| SdfNode::SmoothUnion {
+            left: Box::new(SdfNode::Sphere {
+                center: Point(Vec3::new(-1.1, 0.0, 0.0)),
+                radius: 1.0,
+            }),
+            right: Box::new(SdfNode::Sphere {
+                center: Point(Vec3::new(1.1, 0.0, 0.0)),
+                radius: 1.0,
+            }),
+            smoothness,
+        };
+        let middle = Vec3::default();
+        assert!((pair(0.0).distance(&middle) - 0.1).abs() < 1e-9);
+        assert!(pair(0.5).distance(&middle) < 0.0);
+    }
+
+    #[test]
+    fn repeat_and_twist() {
+        let grid = SdfNode::Repeat {
+            period: Vec3::new(4.0, 0.0, 0.0),
+            node: Box::new(unit_sphere()),
+        };
+        assert!((grid.distance(&Vec3::new(8.0, 2.0, 0.0)) - 1.0).abs() < 1e-9);
+
+        let twisted = SdfNode::Twist {
+            rate: std::f64::consts::FRAC_PI_2,
+            node: Box::new(SdfNode::Cuboid {
+                center: Point(Vec3::default()),
+                half_size: Vec3::new(1.0, 2.0, 0.1),
+                rounding: 0.0,
+            }),
+        };
+        let mut sdf = Sdf::new(twisted, bounds(2.5));
+        sdf.step_scale = 0.5;
+        // one unit up the thin slab has turned to face the ray
+        let r = Ray::new(Point(Vec3::new(0.0, 1.0, 5.0)), Vec3::new(0.0, 0.0, -1.0));
+        let hit = sdf.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let sdf = Sdf::new(unit_sphere(), bounds(1.0));
+        let json = serde_json::to_string(&sdf).unwrap();
+        let loaded: Sdf = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.root, sdf.root);
+        assert_eq!(loaded.step_scale, 1.0);
+    }
+}
@@ -1,11 +1,15 @@
 use std::f64::consts::PI;
 
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
-use super::hittable::{Geometry, HitRecord};
+use super::hittable::{Geometry, HitRecord, SolidAngleSampler};
 use crate::{
     bvh::aabb::Aabb,
-    core::{Point, Ray, Vec3},
+    core::{
+        math::{from_local_frame, random_unit_vector},
+        Point, Ray, Vec3,
+    },
 };
 
 #[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
@@ -23,10 +27,9 @@ impl Sphere {
         let v = theta / PI;
         (u, v)
     }
-}
 
-impl Geometry for Sphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<super::hittable::HitRecord> {
+    /// Both ray parameters where the ray meets the sphere, nearest first.
+    fn roots(&self, ray: &Ray) -> Option<(f64, f64)> {
         let oc = ray.orig.0 - self.center.0;
         let a = ray.direction.length_squared();
         let half_b = oc.dot(&ray.direction);
@@ -36,10 +39,17 @@ impl Geometry for Sphere {
             return None;
         }
         let sqrt_d = discriminant.sqrt();
-        let mut root = (-half_b - sqrt_d) / a;
+        Some(((-half_b - sqrt_d) / a, (-half_b + sqrt_d) / a))
+    }
+}
+
+impl Geometry for Sphere {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<super::hittable::HitRecord> {
+        let (near, far) = self.roots(ray)?;
+        let mut root = near;
 
         if root < t_min || t_max < root {
-            root = (-half_b + sqrt_d) / a;
+            root = far;
             if root < t_min || t_max < root {
                 return None;
             }
@@ -58,4 +68,51 @@ impl Geometry for Sphere {
             max: Point(self.center.0 + r),
         })
     }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let inside = |t: f64| t_min <= t && t <= t_max;
+        self.roots(ray)
+            .is_some_and(|(near, far)| inside(near) || inside(far))
+    }
+}
+
+impl SolidAngleSampler for Sphere {
+    fn sample_direction<R: Rng>(&self, rng: &mut R, origin: &Point) -> Vec3 {
+        let to_center = self.center.0 - origin.0;
+        let distance_squared = to_center.length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            // inside the sphere every direction hits, pick a point on the surface
+            let target = self.center.0 + random_unit_vector(rng).scale(self.radius.abs());
+            return target - origin.0;
+        }
+
+        // uniformly sample the cone of directions that can see the sphere
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let r1 = rng.gen::<f64>();
+        let r2 = rng.gen::<f64>();
+        let z = 1.0 + r2 * (cos_theta_max - 1.0);
+        let phi = 2.0 * PI * r1;
+        let sin_theta = (1.0 - z * z).sqrt();
+        let local = Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z);
+        from_local_frame(&to_center, &local)
+    }
+
+    fn direction_pdf(&self, origin: &Point, direction: &Vec3) -> f64 {
+        let ray = Ray::new(*origin, *direction);
+        let hit = match self.hit(&ray, 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => return 0.0,
+        };
+        let distance_squared = (self.center.0 - origin.0).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            let area = 4.0 * PI * radius_squared;
+            let t_squared = hit.t * hit.t * direction.length_squared();
+            let cosine = (direction.dot(&hit.normal) / direction.length()).abs();
+            return t_squared / (cosine * area);
+        }
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+    }
 }
@@ -0,0 +1,236 @@
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use super::hittable::{Geometry, HitRecord};
+use crate::{
+    bvh::aabb::Aabb,
+    core::{Point, Ray, Vec3},
+};
+
+const ROOT_EPSILON: f64 = 1e-9;
+const NEWTON_STEPS: usize = 4;
+
+/// Real roots of `x^3 + a x^2 + b x + c`, the first `n` of the returned
+/// `(roots, n)`.
+fn solve_cubic(a: f64, b: f64, c: f64) -> ([f64; 3], usize) {
+    // depress with x = y - a/3 to y^3 + p y + q
+    let p = b - a * a / 3.0;
+    let q = 2.0 * a * a * a / 27.0 - a * b / 3.0 + c;
+    let shift = -a / 3.0;
+    let discriminant = q * q / 4.0 + p * p * p / 27.0;
+    if discriminant > ROOT_EPSILON {
+        let sqrt_d = discriminant.sqrt();
+        let y = (-q / 2.0 + sqrt_d).cbrt() + (-q / 2.0 - sqrt_d).cbrt();
+        ([y + shift, 0.0, 0.0], 1)
+    } else if p.abs() < ROOT_EPSILON {
+        ([(-q).cbrt() + shift, 0.0, 0.0], 1)
+    } else {
+        // three real roots
+        let r = (-p / 3.0).sqrt();
+        let phi = (-q / (2.0 * r * r * r)).clamp(-1.0, 1.0).acos();
+        let root = |k: f64| 2.0 * r * ((phi + 2.0 * PI * k) / 3.0).cos() + shift;
+        ([root(0.0), root(1.0), root(2.0)], 3)
+    }
+}
+
+/// Real roots of `x^2 + b x + c`, the first `n` of the returned `(roots, n)`.
+fn solve_quadratic(b: f64, c: f64) -> ([f64; 2], usize) {
+    let discriminant = b * b - 4.0 * c;
+    if discriminant < 0.0 {
+        return ([0.0; 2], 0);
+    }
+    let sqrt_d = discriminant.sqrt();
+    ([(-b - sqrt_d) / 2.0, (-b + sqrt_d) / 2.0], 2)
+}
+
+/// Real roots of `x^4 + a x^3 + b x^2 + c x + d`, by Ferrari's method,
+/// polished with a few Newton steps since the closed form loses precision.
+/// The first `n` of the returned `(roots, n)` are set.
+fn solve_quartic(a: f64, b: f64, c: f64, d: f64) -> ([f64; 4], usize) {
+    // depress with x = y - a/4 to y^4 + p y^2 + q y + r
+    let a2 = a * a;
+    let p = b - 3.0 * a2 / 8.0;
+    let q = c - a * b / 2.0 + a2 * a / 8.0;
+    let r = d - a * c / 4.0 + a2 * b / 16.0 - 3.0 * a2 * a2 / 256.0;
+
+    let mut ys = [0.0; 4];
+    let mut n = 0;
+    if q.abs() < ROOT_EPSILON {
+        // biquadratic
+        let (zs, zn) = solve_quadratic(p, r);
+        for z in zs[..zn].iter().filter(|z| **z >= 0.0) {
+            ys[n] = z.sqrt();
+            ys[n + 1] = -z.sqrt();
+            n += 2;
+        }
+    } else {
+        // pick m so both sides are perfect squares, the largest root of the
+        // resolvent is always positive
+        let (ms, mn) = solve_cubic(p, p * p / 4.0 - r, -q * q / 8.0);
+        let m = ms[..mn].iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        if m <= 0.0 {
+            return ([0.0; 4], 0);
+        }
+        let s = (2.0 * m).sqrt();
+        for (qb, qc) in [
+            (-s, p / 2.0 + m + q / (2.0 * s)),
+            (s, p / 2.0 + m - q / (2.0 * s)),
+        ] {
+            let (roots, rn) = solve_quadratic(qb, qc);
+            ys[n..n + rn].copy_from_slice(&roots[..rn]);
+            n += rn;
+        }
+    }
+
+    for y in ys[..n].iter_mut() {
+        let mut x = *y - a / 4.0;
+        for _ in 0..NEWTON_STEPS {
+            let f = (((x + a) * x + b) * x + c) * x + d;
+            let df = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+            if df.abs() < ROOT_EPSILON {
+                break;
+            }
+            x -= f / df;
+        }
+        *y = x;
+    }
+    (ys, n)
+}
+
+/// A ring around the y axis, `major_radius` from `center` to the middle of
+/// the tube, which is `minor_radius` thick.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Torus {
+    pub center: Point,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+}
+
+impl Torus {
+    pub fn new(center: Point, major_radius: f64, minor_radius: f64) -> Torus {
+        Torus {
+            center,
+            major_radius,
+            minor_radius,
+        }
+    }
+
+    fn quartic_hits(&self, o: &Vec3, d: &Vec3) -> ([f64; 4], usize) {
+        let big_r2 = self.major_radius * self.major_radius;
+        let small_r2 = self.minor_radius * self.minor_radius;
+        // `d` is normalized, so the leading coefficient is 1
+        let e = o.length_squared() + big_r2 - small_r2;
+        let f = o.dot(d);
+        let a = 4.0 * f;
+        let b = 2.0 * e + 4.0 * f * f - 4.0 * big_r2 * (d.x() * d.x() + d.z() * d.z());
+        let c = 4.0 * f * e - 8.0 * big_r2 * (o.x() * d.x() + o.z() * d.z());
+        let dd = e * e - 4.0 * big_r2 * (o.x() * o.x() + o.z() * o.z());
+        solve_quartic(a, b, c, dd)
+    }
+}
+
+impl Geometry for Torus {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let length = ray.direction.length();
+        let d = ray.direction.scale(1.0 / length);
+        let mut o = ray.orig.0 - self.center.0;
+
+        // start from the bounding sphere, far away origins make the quartic
+        // badly conditioned
+        let bound = self.major_radius + self.minor_radius;
+        let along = -o.dot(&d);
+        let closest2 = (o + d.scale(along)).length_squared();
+        if closest2 > bound * bound {
+            return None;
+        }
+        let skip = (along - (bound * bound - closest2).sqrt()).max(0.0);
+        o += d.scale(skip);
+
+        let (roots, n) = self.quartic_hits(&o, &d);
+        let t = roots[..n]
+            .iter()
+            .map(|s| (s + skip) / length)
+            .filter(|t| *t >= t_min && *t <= t_max)
+            .fold(f64::INFINITY, f64::min);
+        if !t.is_finite() {
+            return None;
+        }
+
+        let point = ray.at(t);
+        let p = point.0 - self.center.0;
+        let ring = Vec3::new(p.x(), 0.0, p.z());
+        let ring_len = ring.length();
+        let tube_center = if ring_len > 0.0 {
+            ring.scale(self.major_radius / ring_len)
+        } else {
+            Vec3::new(self.major_radius, 0.0, 0.0)
+        };
+        let normal = (p - tube_center).unit();
+
+        let u = ((-p.z()).atan2(p.x()) + PI) / (2.0 * PI);
+        let v = (normal.y().atan2(ring_len - self.major_radius) + PI) / (2.0 * PI);
+        Some(HitRecord::new(ray, point, normal, t, u, v))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let outer = self.major_radius + self.minor_radius;
+        let extent = Vec3::new(outer, self.minor_radius, outer);
+        Some(Aabb {
+            min: Point(self.center.0 - extent),
+            max: Point(self.center.0 + extent),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quartic_roots() {
+        // (x - 1)(x - 2)(x + 3)(x - 0.5)
+        let (mut roots, n) = solve_quartic(-0.5, -7.0, 9.5, -3.0);
+        assert_eq!(n, 4);
+        roots.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let expected = [-3.0, 0.5, 1.0, 2.0];
+        for (r, e) in roots.iter().zip(expected.iter()) {
+            assert!((r - e).abs() < 1e-9, "{:?}", roots);
+        }
+    }
+
+    #[test]
+    fn hit_torus_through_hole() {
+        let torus = Torus::new(Point(Vec3::new(0.0, 0.0, -10.0)), 2.0, 0.5);
+        // straight through the middle misses
+        let r = Ray::new(
+            Point(Vec3::new(0.0, 10.0, -10.0)),
+            Vec3::new(0.0, -1.0, 0.0),
+        );
+        assert!(torus.hit(&r, 0.0, f64::MAX).is_none());
+
+        // along the x axis hits the outside of the ring first
+        let r = Ray::new(
+            Point(Vec3::new(-20.0, 0.0, -10.0)),
+            Vec3::new(2.0, 0.0, 0.0),
+        );
+        let hit = torus.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 17.5 / 2.0).abs() < 1e-9);
+        assert!((hit.normal - Vec3::new(-1.0, 0.0, 0.0)).near_zero());
+
+        // and from inside the hole, the inner wall
+        let r = Ray::new(Point(Vec3::new(0.0, 0.0, -10.0)), Vec3::new(1.0, 0.0, 0.0));
+        let hit = torus.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 1.5).abs() < 1e-9);
+        assert!(hit.front_face);
+    }
+
+    #[test]
+    fn hit_torus_from_above() {
+        let torus = Torus::new(Point(Vec3::default()), 2.0, 0.5);
+        let r = Ray::new(Point(Vec3::new(2.0, 100.0, 0.0)), Vec3::new(0.0, -1.0, 0.0));
+        let hit = torus.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 99.5).abs() < 1e-9);
+        assert!((hit.normal - Vec3::new(0.0, 1.0, 0.0)).near_zero());
+    }
+}
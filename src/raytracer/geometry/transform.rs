@@ -0,0 +1,317 @@
+use std::convert::TryFrom;
+
+use nalgebra::{Matrix4, UnitQuaternion, Vector3, Vector4};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    hittable::{Geometry, HitRecord},
+    object::GeometricObject,
+};
+use crate::{
+    bvh::{
+        aabb::{bounding, Aabb},
+        bbox_tree::TraversalCounters,
+    },
+    core::{Point, Ray, Vec3},
+};
+
+/// An affine transform, kept alongside its inverse so rays can be taken into
+/// object space without inverting on every hit.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Matrix4<f64>", into = "Matrix4<f64>")]
+pub struct Transform {
+    matrix: Matrix4<f64>,
+    inverse: Matrix4<f64>,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+impl TryFrom<Matrix4<f64>> for Transform {
+    type Error = String;
+
+    fn try_from(matrix: Matrix4<f64>) -> Result<Self, Self::Error> {
+        Transform::from_matrix(matrix)
+            .ok_or_else(|| format!("transform matrix is not invertible: {}", matrix))
+    }
+}
+
+impl From<Transform> for Matrix4<f64> {
+    fn from(t: Transform) -> Self {
+        t.matrix
+    }
+}
+
+#[inline]
+fn degrees_to_radians(deg: f64) -> f64 {
+    deg * std::f64::consts::PI / 180.0
+}
+
+pub(crate) fn rotation_quaternion(axis: Vec3, degrees: f64) -> UnitQuaternion<f64> {
+    let axis = nalgebra::Unit::new_normalize(Vector3::new(axis.x(), axis.y(), axis.z()));
+    UnitQuaternion::from_axis_angle(&axis, degrees_to_radians(degrees))
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            matrix: Matrix4::identity(),
+            inverse: Matrix4::identity(),
+        }
+    }
+
+    pub fn from_matrix(matrix: Matrix4<f64>) -> Option<Transform> {
+        let inverse = matrix.try_inverse()?;
+        Some(Transform { matrix, inverse })
+    }
+
+    pub fn translate(offset: Vec3) -> Transform {
+        let v = Vector3::new(offset.x(), offset.y(), offset.z());
+        Transform {
+            matrix: Matrix4::new_translation(&v),
+            inverse: Matrix4::new_translation(&-v),
+        }
+    }
+
+    pub fn scale(factor: Vec3) -> Transform {
+        let v = Vector3::new(factor.x(), factor.y(), factor.z());
+        Transform::from_matrix(Matrix4::new_nonuniform_scaling(&v))
+            .expect("scale factors must be non-zero")
+    }
+
+    /// Rotate counter clockwise around `axis`, in degrees.
+    pub fn rotate(axis: Vec3, degrees: f64) -> Transform {
+        Transform::from_rotation(&rotation_quaternion(axis, degrees))
+    }
+
+    pub fn from_rotation(rotation: &UnitQuaternion<f64>) -> Transform {
+        let matrix = rotation.to_homogeneous();
+        Transform {
+            matrix,
+            inverse: matrix.transpose(),
+        }
+    }
+
+    pub fn rotate_x(degrees: f64) -> Transform {
+        Transform::rotate(Vec3::new(1.0, 0.0, 0.0), degrees)
+    }
+
+    pub fn rotate_y(degrees: f64) -> Transform {
+        Transform::rotate(Vec3::new(0.0, 1.0, 0.0), degrees)
+    }
+
+    pub fn rotate_z(degrees: f64) -> Transform {
+        Transform::rotate(Vec3::new(0.0, 0.0, 1.0), degrees)
+    }
+
+    /// Apply `self` first, and then `next`.
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            matrix: next.matrix * self.matrix,
+            inverse: self.inverse * next.inverse,
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            matrix: self.inverse,
+            inverse: self.matrix,
+        }
+    }
+
+    pub fn matrix(&self) -> &Matrix4<f64> {
+        &self.matrix
+    }
+
+    pub fn point(&self, p: &Point) -> Point {
+        Point(apply(&self.matrix, &p.0, 1.0))
+    }
+
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        apply(&self.matrix, v, 0.0)
+    }
+
+    /// Normals transform by the inverse transpose to stay perpendicular to
+    /// the surface.
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        apply(&self.inverse.transpose(), n, 0.0).unit()
+    }
+
+    pub fn ray(&self, r: &Ray) -> Ray {
+        Ray {
+            orig: self.point(&r.orig),
+            direction: self.vector(&r.direction),
+            time: r.time,
+        }
+    }
+
+    pub fn bbox(&self, b: &Aabb) -> Aabb {
+        let mut corners = Vec::with_capacity(8);
+        for idx in 0..8 {
+            let pick = |bit: usize, d: usize| {
+                if idx & bit == 0 {
+                    b.min.0[d]
+                } else {
+                    b.max.0[d]
+                }
+            };
+            let corner = self.point(&Point(Vec3::new(pick(1, 0), pick(2, 1), pick(4, 2))));
+            corners.push(Aabb {
+                min: corner,
+                max: corner,
+            });
+        }
+        bounding(corners.iter()).unwrap()
+    }
+}
+
+#[inline]
+fn apply(m: &Matrix4<f64>, v: &Vec3, w: f64) -> Vec3 {
+    let r = m * Vector4::new(v.x(), v.y(), v.z(), w);
+    Vec3::new(r.x, r.y, r.z)
+}
+
+/// Place any geometry in the world with an affine transform.
+#[derive(Serialize, Deserialize)]
+pub struct Transformed {
+    transform: Transform,
+    inner: Box<GeometricObject>,
+    #[serde(skip)]
+    bbox: Option<Aabb>,
+}
+
+impl Transformed {
+    pub fn new<G: Into<GeometricObject>>(inner: G, transform: Transform) -> Transformed {
+        let inner = inner.into();
+        let bbox = inner.bounding_box().map(|b| transform.bbox(&b));
+        Transformed {
+            transform,
+            inner: Box::new(inner),
+            bbox,
+        }
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+
+    pub fn inner(&self) -> &GeometricObject {
+        &self.inner
+    }
+}
+
+impl Geometry for Transformed {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.hit_counted(ray, t_min, t_max, &mut None)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        match &self.bbox {
+            Some(b) => Some(b.clone()),
+            // deserialized objects don't carry the cache
+            None => self.inner.bounding_box().map(|b| self.transform.bbox(&b)),
+        }
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.occluded_counted(ray, t_min, t_max, &mut None)
+    }
+
+    fn hit_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> Option<HitRecord> {
+        // the direction is not normalized, so `t` is the same in both spaces
+        let local = self.transform.inverse().ray(ray);
+        let mut record = self.inner.hit_counted(&local, t_min, t_max, counters)?;
+        record.point = self.transform.point(&record.point);
+        record.normal = self.transform.normal(&record.normal);
+        Some(record)
+    }
+
+    fn occluded_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> bool {
+        let local = self.transform.inverse().ray(ray);
+        self.inner.occluded_counted(&local, t_min, t_max, counters)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::{rect::RectBox, sphere::Sphere};
+
+    fn unit_sphere() -> Sphere {
+        Sphere {
+            center: Point(Vec3::default()),
+            radius: 1.0,
+        }
+    }
+
+    #[test]
+    fn translated_sphere() {
+        let t = Transformed::new(
+            unit_sphere(),
+            Transform::translate(Vec3::new(0.0, 0.0, -5.0)),
+        );
+        let r = Ray::new(Point(Vec3::default()), Vec3::new(0.0, 0.0, -1.0));
+        let hit = t.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-9);
+        assert!((hit.point.0 - Vec3::new(0.0, 0.0, -4.0)).near_zero());
+        assert!((hit.normal - Vec3::new(0.0, 0.0, 1.0)).near_zero());
+    }
+
+    #[test]
+    fn scaled_sphere_normal() {
+        let transform = Transform::scale(Vec3::new(2.0, 1.0, 1.0));
+        let t = Transformed::new(unit_sphere(), transform);
+        let r = Ray::new(Point(Vec3::new(5.0, 0.0, 0.0)), Vec3::new(-1.0, 0.0, 0.0));
+        let hit = t.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 3.0).abs() < 1e-9);
+        assert!((hit.normal - Vec3::new(1.0, 0.0, 0.0)).near_zero());
+    }
+
+    #[test]
+    fn rotated_box_bounds() {
+        let b = RectBox::new(
+            Point(Vec3::new(-1.0, -1.0, -1.0)),
+            Point(Vec3::new(1.0, 1.0, 1.0)),
+        );
+        let t = Transformed::new(b, Transform::rotate_y(45.0));
+        let bbox = t.bounding_box().unwrap();
+        let half_diag = 2.0f64.sqrt();
+        assert!((bbox.max.0.x() - half_diag).abs() < 1e-9);
+        assert!((bbox.min.0.z() + half_diag).abs() < 1e-9);
+        assert!((bbox.max.0.y() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn compose_and_invert() {
+        let t = Transform::rotate_z(30.0)
+            .then(&Transform::scale(Vec3::new(1.0, 2.0, 3.0)))
+            .then(&Transform::translate(Vec3::new(4.0, 5.0, 6.0)));
+        let p = Point(Vec3::new(1.0, -2.0, 0.5));
+        let back = t.inverse().point(&t.point(&p));
+        assert!((back.0 - p.0).near_zero());
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let t = Transformed::new(unit_sphere(), Transform::rotate_x(90.0));
+        let json = serde_json::to_string(&t).unwrap();
+        let loaded: Transformed = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.transform().matrix(), t.transform().matrix());
+        assert_eq!(loaded.bounding_box(), t.bounding_box());
+    }
+}
@@ -0,0 +1,385 @@
+use std::{convert::TryFrom, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+
+use super::hittable::{Geometry, HitRecord};
+use crate::{
+    bvh::{
+        aabb::Aabb,
+        bbox_tree::{BboxTree, TraversalCounters},
+    },
+    core::{
+        fp::{fmax, fmin},
+        Point, Ray, Vec3,
+    },
+};
+
+const BBOX_WIDTH: f64 = 0.0001;
+const PARALLEL_EPSILON: f64 = 1e-12;
+
+/// Moller-Trumbore intersection, returns `t` and the barycentric
+/// weights of `p1` and `p2`.
+pub(super) fn intersect(
+    ray: &Ray,
+    p0: &Point,
+    p1: &Point,
+    p2: &Point,
+    t_min: f64,
+    t_max: f64,
+) -> Option<(f64, f64, f64)> {
+    let edge1 = p1.0 - p0.0;
+    let edge2 = p2.0 - p0.0;
+    let pvec = ray.direction.cross(&edge2);
+    let det = edge1.dot(&pvec);
+    if det.abs() < PARALLEL_EPSILON {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+
+    let tvec = ray.orig.0 - p0.0;
+    let b1 = tvec.dot(&pvec) * inv_det;
+    if !(0.0..=1.0).contains(&b1) {
+        return None;
+    }
+
+    let qvec = tvec.cross(&edge1);
+    let b2 = ray.direction.dot(&qvec) * inv_det;
+    if b2 < 0.0 || b1 + b2 > 1.0 {
+        return None;
+    }
+
+    let t = edge2.dot(&qvec) * inv_det;
+    if t < t_min || t > t_max {
+        return None;
+    }
+    Some((t, b1, b2))
+}
+
+fn triangle_bbox(p0: &Point, p1: &Point, p2: &Point) -> Aabb {
+    let mut min = Vec3::default();
+    let mut max = Vec3::default();
+    for d in 0..3 {
+        min[d] = fmin(fmin(p0.0[d], p1.0[d]), p2.0[d]);
+        max[d] = fmax(fmax(p0.0[d], p1.0[d]), p2.0[d]);
+        if max[d] - min[d] < BBOX_WIDTH {
+            min[d] -= BBOX_WIDTH;
+            max[d] += BBOX_WIDTH;
+        }
+    }
+    Aabb {
+        min: Point(min),
+        max: Point(max),
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Triangle {
+    pub vertices: [Point; 3],
+}
+
+impl Triangle {
+    pub fn new(p0: Point, p1: Point, p2: Point) -> Triangle {
+        Triangle {
+            vertices: [p0, p1, p2],
+        }
+    }
+}
+
+impl Geometry for Triangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let [p0, p1, p2] = &self.vertices;
+        let (t, u, v) = intersect(ray, p0, p1, p2, t_min, t_max)?;
+        let normal = (p1.0 - p0.0).cross(&(p2.0 - p0.0)).unit();
+        Some(HitRecord::new(ray, ray.at(t), normal, t, u, v))
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let [p0, p1, p2] = &self.vertices;
+        Some(triangle_bbox(p0, p1, p2))
+    }
+}
+
+/// The raw buffers of an indexed mesh.
+///
+/// `normals` and `uvs` are optional, but when present they are indexed the
+/// same way as `vertices`.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct MeshData {
+    pub vertices: Vec<Point>,
+    #[serde(default)]
+    pub normals: Vec<Vec3>,
+    #[serde(default)]
+    pub uvs: Vec<(f64, f64)>,
+    pub faces: Vec<[usize; 3]>,
+}
+
+impl MeshData {
+    fn validate(&self) -> anyhow::Result<()> {
+        if !self.normals.is_empty() && self.normals.len() != self.vertices.len() {
+            anyhow::bail!(
+                "mesh has {} normals for {} vertices",
+                self.normals.len(),
+                self.vertices.len()
+            );
+        }
+        if !self.uvs.is_empty() && self.uvs.len() != self.vertices.len() {
+            anyhow::bail!(
+                "mesh has {} uvs for {} vertices",
+                self.uvs.len(),
+                self.vertices.len()
+            );
+        }
+        for (idx, face) in self.faces.iter().enumerate() {
+            if face.iter().any(|v| *v >= self.vertices.len()) {
+                anyhow::bail!(
+                    "mesh face {} {:?} references a vertex out of range (len {})",
+                    idx,
+                    face,
+                    self.vertices.len()
+                );
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A single face of a `TriangleMesh`, pointing back into the shared buffers.
+pub struct MeshTriangle {
+    mesh: Arc<MeshData>,
+    face: usize,
+}
+
+impl MeshTriangle {
+    fn points(&self) -> (&Point, &Point, &Point) {
+        let [i0, i1, i2] = self.mesh.faces[self.face];
+        (
+            &self.mesh.vertices[i0],
+            &self.mesh.vertices[i1],
+            &self.mesh.vertices[i2],
+        )
+    }
+}
+
+impl Geometry for MeshTriangle {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        let (p0, p1, p2) = self.points();
+        let (t, b1, b2) = intersect(ray, p0, p1, p2, t_min, t_max)?;
+        let b0 = 1.0 - b1 - b2;
+        let [i0, i1, i2] = self.mesh.faces[self.face];
+
+        let geometric = (p1.0 - p0.0).cross(&(p2.0 - p0.0)).unit();
+
+        let (u, v) = if self.mesh.uvs.is_empty() {
+            (b1, b2)
+        } else {
+            let (u0, v0) = self.mesh.uvs[i0];
+            let (u1, v1) = self.mesh.uvs[i1];
+            let (u2, v2) = self.mesh.uvs[i2];
+            (b0 * u0 + b1 * u1 + b2 * u2, b0 * v0 + b1 * v1 + b2 * v2)
+        };
+
+        let mut record = HitRecord::new(ray, ray.at(t), geometric, t, u, v);
+
+        if !self.mesh.normals.is_empty() {
+            let mut shading = (self.mesh.normals[i0].scale(b0)
+                + self.mesh.normals[i1].scale(b1)
+                + self.mesh.normals[i2].scale(b2))
+            .unit();
+            // keep the interpolated normal on the same side as the face,
+            // front_face is always decided by the true geometry
+            if shading.dot(&record.normal) < 0.0 {
+                shading.scale_mut(-1.0);
+            }
+            record.normal = shading;
+        }
+        Some(record)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        let (p0, p1, p2) = self.points();
+        Some(triangle_bbox(p0, p1, p2))
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(try_from = "MeshData")]
+pub struct TriangleMesh {
+    mesh: Arc<MeshData>,
+    tree: BboxTree<MeshTriangle>,
+}
+
+impl TriangleMesh {
+    pub fn new(data: MeshData) -> anyhow::Result<TriangleMesh> {
+        data.validate()?;
+        let mesh = Arc::new(data);
+        let faces = (0..mesh.faces.len())
+            .map(|face| MeshTriangle {
+                mesh: mesh.clone(),
+                face,
+            })
+            .collect::<Vec<_>>();
+        let tree = BboxTree::new(faces);
+        Ok(TriangleMesh { mesh, tree })
+    }
+
+    pub fn data(&self) -> &MeshData {
+        self.mesh.as_ref()
+    }
+
+    pub fn len(&self) -> usize {
+        self.mesh.faces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mesh.faces.is_empty()
+    }
+}
+
+impl TryFrom<MeshData> for TriangleMesh {
+    type Error = anyhow::Error;
+
+    fn try_from(data: MeshData) -> Result<Self, Self::Error> {
+        TriangleMesh::new(data)
+    }
+}
+
+impl Serialize for TriangleMesh {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.mesh.serialize(serializer)
+    }
+}
+
+impl Geometry for TriangleMesh {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.hit_counted(ray, t_min, t_max, &mut None)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.tree.bounding_box()
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.occluded_counted(ray, t_min, t_max, &mut None)
+    }
+
+    fn hit_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> Option<HitRecord> {
+        self.tree
+            .hit_with(ray, t_min, t_max, counters, |tri, ray, t_min, t_max, _| {
+                tri.hit(ray, t_min, t_max).map(|hit| ((), hit))
+            })
+            .map(|(_, record)| record)
+    }
+
+    fn occluded_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> bool {
+        self.tree
+            .occluded_with(ray, t_min, t_max, counters, |tri, ray, t_min, t_max, _| {
+                tri.occluded(ray, t_min, t_max)
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_quad() -> MeshData {
+        MeshData {
+            vertices: vec![
+                Point(Vec3::new(-1.0, -1.0, 0.0)),
+                Point(Vec3::new(1.0, -1.0, 0.0)),
+                Point(Vec3::new(1.0, 1.0, 0.0)),
+                Point(Vec3::new(-1.0, 1.0, 0.0)),
+            ],
+            normals: vec![],
+            uvs: vec![(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)],
+            faces: vec![[0, 1, 2], [0, 2, 3]],
+        }
+    }
+
+    #[test]
+    fn hit_triangle() {
+        let tri = Triangle::new(
+            Point(Vec3::new(-1.0, -1.0, -2.0)),
+            Point(Vec3::new(1.0, -1.0, -2.0)),
+            Point(Vec3::new(0.0, 1.0, -2.0)),
+        );
+        let r = Ray::new(Point(Vec3::default()), Vec3::new(0.0, 0.0, -1.0));
+        let hit = tri.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 2.0).abs() < 1e-9);
+        assert!(hit.front_face);
+        assert_eq!(hit.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn miss_triangle() {
+        let tri = Triangle::new(
+            Point(Vec3::new(-1.0, -1.0, -2.0)),
+            Point(Vec3::new(1.0, -1.0, -2.0)),
+            Point(Vec3::new(0.0, 1.0, -2.0)),
+        );
+        let r = Ray::new(Point(Vec3::new(2.0, 0.0, 0.0)), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(tri.hit(&r, 0.0, f64::MAX), None);
+    }
+
+    #[test]
+    fn flat_triangle_has_volume() {
+        let tri = Triangle::new(
+            Point(Vec3::new(-1.0, 0.0, -1.0)),
+            Point(Vec3::new(1.0, 0.0, -1.0)),
+            Point(Vec3::new(0.0, 0.0, 1.0)),
+        );
+        let bbox = tri.bounding_box().unwrap();
+        assert!(bbox.max.0.y() > bbox.min.0.y());
+    }
+
+    #[test]
+    fn mesh_interpolates_uv() {
+        let mesh = TriangleMesh::new(unit_quad()).unwrap();
+        let r = Ray::new(Point(Vec3::new(0.5, 0.5, 1.0)), Vec3::new(0.0, 0.0, -1.0));
+        let hit = mesh.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.u - 0.75).abs() < 1e-9);
+        assert!((hit.v - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn mesh_smooth_normal() {
+        let mut data = unit_quad();
+        let tilt = Vec3::new(1.0, 0.0, 1.0).unit();
+        data.normals = vec![tilt; 4];
+        let mesh = TriangleMesh::new(data).unwrap();
+
+        // hitting the back side flips the shading normal along with the face
+        let r = Ray::new(Point(Vec3::new(0.0, 0.0, -1.0)), Vec3::new(0.0, 0.0, 1.0));
+        let hit = mesh.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!(!hit.front_face);
+        assert!((hit.normal - tilt.scale(-1.0)).near_zero());
+    }
+
+    #[test]
+    fn mesh_rejects_bad_index() {
+        let mut data = unit_quad();
+        data.faces.push([0, 1, 4]);
+        assert!(TriangleMesh::new(data).is_err());
+    }
+
+    #[test]
+    fn mesh_serde_round_trip() {
+        let mesh = TriangleMesh::new(unit_quad()).unwrap();
+        let json = serde_json::to_string(&mesh).unwrap();
+        let loaded: TriangleMesh = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.len(), 2);
+        assert_eq!(loaded.bounding_box(), mesh.bounding_box());
+    }
+}
@@ -1,8 +1,12 @@
-use std::io;
+use std::{io, path::Path, str::FromStr};
+
+use anyhow::Context;
+use image::Rgb;
 
 use crate::{
     camera::Dimmensions,
     core::{Color, Vec3},
+    tonemap::ToneMap,
 };
 
 const PPM_COLOR_SCALE: f64 = 255.999;
@@ -28,19 +32,168 @@ impl Image {
     }
 }
 
-pub fn to_image<P: AsRef<std::path::Path>>(img: &Image, path: P) {
+pub fn to_image<P: AsRef<std::path::Path>>(
+    img: &Image,
+    path: P,
+    tonemap: &ToneMap,
+) -> anyhow::Result<()> {
+    let path = path.as_ref();
     log::trace!("convert image");
     let mut dst = image::RgbImage::new(img.dimm.width as u32, img.dimm.height as u32);
     for j in 0..img.dimm.height {
         for (i, c) in img.data[j].iter().enumerate() {
-            let mut c = Color(c.0.scale(1.0 / (img.samples as f64)));
-            c.0.sqrt_mut();
+            let c = tonemap.apply(Color(c.0.scale(1.0 / (img.samples as f64))));
             dst.put_pixel(i as u32, (img.dimm.height - j - 1) as u32, c.to_pixel())
         }
     }
     log::trace!("write png");
-    dst.save_with_format(path.as_ref(), image::ImageFormat::Png)
-        .unwrap();
+    dst.save_with_format(path, image::ImageFormat::Png)
+        .with_context(|| format!("could not write {}", path.display()))
+}
+
+/// File formats a render can be written as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// 8-bit, gamma corrected and clamped
+    Png,
+    /// OpenEXR, linear 32-bit float radiance
+    Exr,
+    /// Radiance RGBE, linear radiance
+    Hdr,
+}
+
+impl OutputFormat {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<OutputFormat> {
+        let ext = path.as_ref().extension()?.to_str()?;
+        ext.parse().ok()
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Ok(OutputFormat::Png),
+            "exr" => Ok(OutputFormat::Exr),
+            "hdr" => Ok(OutputFormat::Hdr),
+            _ => anyhow::bail!("unknown image format {:?}, expected png, exr or hdr", s),
+        }
+    }
+}
+
+/// The averaged radiance of every pixel, top row first, with no tone mapping.
+pub fn linear_pixels(img: &Image) -> Vec<Rgb<f32>> {
+    let scale = 1.0 / (img.samples as f64);
+    img.data
+        .iter()
+        .rev()
+        .flat_map(|line| line.iter())
+        .map(|c| {
+            let c = c.0.scale(scale);
+            Rgb([c.x() as f32, c.y() as f32, c.z() as f32])
+        })
+        .collect()
+}
+
+/// Write the image, using `format` if given, otherwise guessing from the
+/// extension of `path` and falling back to png.
+///
+/// `tonemap` is only used by png, the HDR formats keep linear radiance.
+pub fn write_image<P: AsRef<Path>>(
+    img: &Image,
+    path: P,
+    format: Option<OutputFormat>,
+    tonemap: &ToneMap,
+) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let format = format
+        .or_else(|| OutputFormat::from_path(path))
+        .unwrap_or(OutputFormat::Png);
+    log::trace!("write {:?} to {}", format, path.display());
+    match format {
+        OutputFormat::Png => to_image(img, path, tonemap)?,
+        OutputFormat::Exr => {
+            let pixels = linear_pixels(img);
+            let raw = pixels.iter().flat_map(|p| p.0).collect::<Vec<f32>>();
+            let buf =
+                image::Rgb32FImage::from_raw(img.dimm.width as u32, img.dimm.height as u32, raw)
+                    .context("image buffer does not match its dimensions")?;
+            buf.save_with_format(path, image::ImageFormat::OpenExr)
+                .with_context(|| format!("could not write {}", path.display()))?;
+        }
+        OutputFormat::Hdr => {
+            let f = std::fs::File::create(path)
+                .with_context(|| format!("could not create {}", path.display()))?;
+            image::codecs::hdr::HdrEncoder::new(io::BufWriter::new(f))
+                .encode(&linear_pixels(img), img.dimm.width, img.dimm.height)
+                .with_context(|| format!("could not write {}", path.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// False colour for `x` in `[0, 1]`, from black through blue, green and
+/// yellow to red, then white for the very highest values.
+pub fn heat_color(x: f64) -> Color {
+    const STOPS: [[f64; 3]; 6] = [
+        [0.0, 0.0, 0.0],
+        [0.0, 0.2, 1.0],
+        [0.0, 0.9, 0.4],
+        [1.0, 0.9, 0.0],
+        [1.0, 0.0, 0.0],
+        [1.0, 1.0, 1.0],
+    ];
+    let x = x.clamp(0.0, 1.0) * (STOPS.len() - 1) as f64;
+    let idx = (x.floor() as usize).min(STOPS.len() - 2);
+    let f = x - idx as f64;
+    let (a, b) = (STOPS[idx], STOPS[idx + 1]);
+    Color(Vec3::new(
+        a[0] + (b[0] - a[0]) * f,
+        a[1] + (b[1] - a[1]) * f,
+        a[2] + (b[2] - a[2]) * f,
+    ))
+}
+
+/// Write the per pixel counts of a heatmap render as a false colour png.
+/// The colour map tops out at the 99th percentile, so a few very busy
+/// pixels don't leave the rest of the image dark.
+pub fn write_heatmap<P: AsRef<Path>>(img: &Image, path: P) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let scale = 1.0 / (img.samples as f64);
+    let mut counts = img
+        .data
+        .iter()
+        .flatten()
+        .map(|c| c.0.x() * scale)
+        .collect::<Vec<_>>();
+    counts.sort_unstable_by(f64::total_cmp);
+    let max = counts
+        .get(counts.len() * 99 / 100)
+        .cloned()
+        .unwrap_or_default();
+    log::info!(
+        "heatmap 99th percentile {:.1}, max {:.1} per sample",
+        max,
+        counts.last().cloned().unwrap_or_default()
+    );
+    let mut dst = image::RgbImage::new(img.dimm.width as u32, img.dimm.height as u32);
+    for j in 0..img.dimm.height {
+        for (i, c) in img.data[j].iter().enumerate() {
+            let x = if max > 0.0 {
+                c.0.x() * scale / max
+            } else {
+                0.0
+            };
+            dst.put_pixel(
+                i as u32,
+                (img.dimm.height - j - 1) as u32,
+                heat_color(x).to_pixel(),
+            )
+        }
+    }
+    dst.save_with_format(path, image::ImageFormat::Png)
+        .with_context(|| format!("could not write {}", path.display()))
 }
 
 // pub fn write_ppm_image<W: io::Write>(w: &mut W, image: &Image) -> std::io::Result<()> {
@@ -68,3 +221,61 @@ pub fn write_ppm_pixel<W: io::Write>(w: &mut W, color: &Color) -> std::io::Resul
     )?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gradient() -> Image {
+        let mut img = Image::from_dimm(Dimmensions {
+            width: 4,
+            height: 2,
+        });
+        img.samples = 2;
+        for (j, line) in img.data.iter_mut().enumerate() {
+            for (i, c) in line.iter_mut().enumerate() {
+                *c = Color(Vec3::new(i as f64 * 10.0, j as f64, 0.5));
+            }
+        }
+        img
+    }
+
+    #[test]
+    fn heat_color_ends() {
+        assert_eq!(heat_color(0.0), Color(Vec3::new(0.0, 0.0, 0.0)));
+        assert_eq!(heat_color(1.0), Color(Vec3::new(1.0, 1.0, 1.0)));
+        assert_eq!(heat_color(2.0), heat_color(1.0));
+        // halfway between the green and yellow stops
+        assert!((heat_color(0.5).0 - Vec3::new(0.5, 0.9, 0.2)).near_zero());
+    }
+
+    #[test]
+    fn format_from_extension() {
+        assert_eq!(
+            OutputFormat::from_path("a/out.EXR"),
+            Some(OutputFormat::Exr)
+        );
+        assert_eq!(OutputFormat::from_path("out.hdr"), Some(OutputFormat::Hdr));
+        assert_eq!(OutputFormat::from_path("out.png"), Some(OutputFormat::Png));
+        assert_eq!(OutputFormat::from_path("out"), None);
+        assert!("tiff".parse::<OutputFormat>().is_err());
+    }
+
+    #[test]
+    fn linear_pixels_keep_range() {
+        let pixels = linear_pixels(&gradient());
+        // the last scanline is the top of the image
+        assert_eq!(pixels[3], Rgb([15.0, 0.5, 0.25]));
+        assert_eq!(pixels[4], Rgb([0.0, 0.0, 0.25]));
+    }
+
+    #[test]
+    fn exr_round_trip() {
+        let path = std::env::temp_dir().join(format!("raytracer-{}.exr", std::process::id()));
+        write_image(&gradient(), &path, None, &ToneMap::default()).unwrap();
+        let loaded = image::open(&path).unwrap().into_rgb32f();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.dimensions(), (4, 2));
+        assert_eq!(*loaded.get_pixel(3, 0), Rgb([15.0, 0.5, 0.25]));
+    }
+}
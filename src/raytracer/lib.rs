@@ -11,10 +11,22 @@ pub mod camera;
 pub mod scene;
 pub mod skybox;
 pub mod geometry {
+    pub mod csg;
+    pub mod cylinder;
+    pub mod disk;
+    pub mod heightfield;
     pub mod hittable;
+    pub mod medium;
+    pub mod motion;
     pub mod object;
+    pub mod plane;
+    pub mod quad;
     pub mod rect;
+    pub mod sdf;
     pub mod sphere;
+    pub mod torus;
+    pub mod transform;
+    pub mod triangle;
 }
 pub mod bvh {
     pub mod aabb;
@@ -22,3 +34,4 @@ pub mod bvh {
 }
 pub mod material;
 pub mod render;
+pub mod tonemap;
@@ -0,0 +1,119 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    microfacet::{fresnel_conductor, reflect, Ggx},
+    texture::Texture,
+    Material, Scatter,
+};
+use crate::{
+    core::{Color, Ray, Vec3},
+    geometry::hittable::HitRecord,
+};
+
+/// Complex index of refraction of a metal, `eta + i k` per RGB channel.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ComplexIor {
+    Gold,
+    Copper,
+    Aluminium,
+    Custom { eta: Color, k: Color },
+}
+
+impl ComplexIor {
+    pub fn eta_k(&self) -> (Color, Color) {
+        let rgb = |r, g, b| Color(Vec3::new(r, g, b));
+        match self {
+            ComplexIor::Gold => (rgb(0.143, 0.374, 1.442), rgb(3.983, 2.385, 1.603)),
+            ComplexIor::Copper => (rgb(0.200, 0.924, 1.102), rgb(3.912, 2.452, 2.142)),
+            ComplexIor::Aluminium => (rgb(1.657, 0.880, 0.521), rgb(9.224, 6.270, 4.837)),
+            ComplexIor::Custom { eta, k } => (*eta, *k),
+        }
+    }
+}
+
+/// A metal with GGX microfacet roughness.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoughConductor<T> {
+    pub ior: ComplexIor,
+    pub roughness: T,
+}
+
+impl<T> RoughConductor<T> {
+    pub fn new(ior: ComplexIor, roughness: T) -> RoughConductor<T> {
+        RoughConductor { ior, roughness }
+    }
+}
+
+impl<T: Texture> RoughConductor<T> {
+    fn eval_pdf(&self, ggx: &Ggx, wo: &Vec3, wi: &Vec3) -> (Color, f64) {
+        let cos_o = ggx.n.dot(wo);
+        let cos_i = ggx.n.dot(wi);
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return (Color::default(), 0.0);
+        }
+        let h = (*wo + *wi).unit();
+        let (eta, k) = self.ior.eta_k();
+        let fresnel = fresnel_conductor(wo.dot(&h), &eta, &k);
+        let f = ggx.d(&h) * ggx.g(wo, wi, &h) / (4.0 * cos_o);
+        let pdf = ggx.pdf_h(&h) / (4.0 * wo.dot(&h).abs());
+        (Color(fresnel.0.scale(f)), pdf)
+    }
+}
+
+impl<T: Texture> Material for RoughConductor<T> {
+    fn sample<R: Rng>(&self, rng: &mut R, ray: &Ray, record: &HitRecord) -> Option<Scatter> {
+        let ggx = Ggx::from_texture(record, &self.roughness);
+        let wo = ray.direction.unit().scale(-1.0);
+        let h = ggx.sample_h(rng);
+        let wi = reflect(&wo, &h);
+        let (f, pdf) = self.eval_pdf(&ggx, &wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(Scatter {
+            direction: Ray::with_time(record.point, wi, ray.time),
+            attenuation: Color(f.0.scale(1.0 / pdf)),
+            pdf,
+            specular: false,
+        })
+    }
+
+    fn pdf(&self, ray: &Ray, record: &HitRecord, scattered: &Ray) -> f64 {
+        let ggx = Ggx::from_texture(record, &self.roughness);
+        let wo = ray.direction.unit().scale(-1.0);
+        self.eval_pdf(&ggx, &wo, &scattered.direction.unit()).1
+    }
+
+    fn eval(&self, ray: &Ray, record: &HitRecord, scattered: &Ray) -> Color {
+        let ggx = Ggx::from_texture(record, &self.roughness);
+        let wo = ray.direction.unit().scale(-1.0);
+        self.eval_pdf(&ggx, &wo, &scattered.direction.unit()).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{tests::sample_matches_eval_over_pdf, texture::solid::ConstantTexture};
+
+    fn grey(v: f64) -> ConstantTexture {
+        ConstantTexture::from(Color(Vec3::new(v, v, v)))
+    }
+
+    #[test]
+    fn sample_matches_eval() {
+        let material = RoughConductor::new(ComplexIor::Gold, grey(0.4));
+        let samples = sample_matches_eval_over_pdf(&material, Vec3::new(1.0, 1.0, 0.0), 100);
+        assert!(samples.len() > 50);
+        assert!(samples.iter().all(|s| s.direction.direction.y() > 0.0));
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let json = r#"{"ior":"Copper","roughness":{"Solid":{"vec":[0.2,0.2,0.2]}}}"#;
+        let m: RoughConductor<crate::material::texture::loader::TextureLoader> =
+            serde_json::from_str(json).unwrap();
+        assert!(matches!(m.ior, ComplexIor::Copper));
+    }
+}
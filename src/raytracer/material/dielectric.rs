@@ -19,7 +19,7 @@ fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
 }
 
 impl Material for Dielectric {
-    fn scatter<R: Rng>(&self, rng: &mut R, ray: &Ray, record: &HitRecord) -> Option<Scatter> {
+    fn sample<R: Rng>(&self, rng: &mut R, ray: &Ray, record: &HitRecord) -> Option<Scatter> {
         let refraction_ratio = if record.front_face {
             1.0 / self.ir
         } else {
@@ -43,8 +43,11 @@ impl Material for Dielectric {
             direction: Ray {
                 orig: record.point,
                 direction,
+                time: ray.time,
             },
             attenuation: Color::ones(),
+            pdf: 0.0,
+            specular: true,
         })
     }
 }
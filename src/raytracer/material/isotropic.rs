@@ -0,0 +1,48 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::{texture::Texture, Material, Scatter};
+use crate::{
+    core::{math::random_unit_vector, Color, Ray},
+    geometry::hittable::HitRecord,
+};
+
+/// Phase function for participating media, scatters uniformly in every direction.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Isotropic<T> {
+    pub albedo: T,
+}
+
+impl<T> Isotropic<T> {
+    pub fn new(texture: T) -> Isotropic<T> {
+        Isotropic { albedo: texture }
+    }
+}
+
+impl<T: Texture> Material for Isotropic<T> {
+    fn sample<R: Rng>(&self, rng: &mut R, ray: &Ray, record: &HitRecord) -> Option<Scatter> {
+        let direction = Ray {
+            orig: record.point,
+            direction: random_unit_vector(rng),
+            time: ray.time,
+        };
+
+        Some(Scatter {
+            direction,
+            attenuation: self.albedo.value(record.u, record.v, &record.point),
+            pdf: 1.0 / (4.0 * PI),
+            specular: false,
+        })
+    }
+
+    fn pdf(&self, _ray: &Ray, _record: &HitRecord, _scattered: &Ray) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn eval(&self, _ray: &Ray, record: &HitRecord, _scattered: &Ray) -> Color {
+        let albedo = self.albedo.value(record.u, record.v, &record.point);
+        Color(albedo.0.scale(1.0 / (4.0 * PI)))
+    }
+}
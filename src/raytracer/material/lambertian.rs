@@ -1,9 +1,11 @@
+use std::f64::consts::PI;
+
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
-use super::{texture::Texture, Material, Scatter};
+use super::{cosine, texture::Texture, Material, Scatter};
 use crate::{
-    core::{math::random_unit_vector, Ray},
+    core::{math::random_unit_vector, Color, Ray},
     geometry::hittable::HitRecord,
 };
 
@@ -19,7 +21,7 @@ impl<T> Lambertian<T> {
 }
 
 impl<T: Texture> Material for Lambertian<T> {
-    fn scatter<R: Rng>(&self, rng: &mut R, _ray: &Ray, record: &HitRecord) -> Option<Scatter> {
+    fn sample<R: Rng>(&self, rng: &mut R, ray: &Ray, record: &HitRecord) -> Option<Scatter> {
         let mut scatter = record.normal + random_unit_vector(rng);
         if scatter.near_zero() {
             scatter = record.normal;
@@ -27,11 +29,43 @@ impl<T: Texture> Material for Lambertian<T> {
         let direction = Ray {
             orig: record.point,
             direction: scatter,
+            time: ray.time,
         };
 
+        // cosine weighted, so eval / pdf is just the albedo
+        let pdf = cosine(record, &direction) / PI;
         Some(Scatter {
             direction,
             attenuation: self.albedo.value(record.u, record.v, &record.point),
+            pdf,
+            specular: false,
         })
     }
+
+    fn pdf(&self, _ray: &Ray, record: &HitRecord, scattered: &Ray) -> f64 {
+        cosine(record, scattered) / PI
+    }
+
+    fn eval(&self, _ray: &Ray, record: &HitRecord, scattered: &Ray) -> Color {
+        let albedo = self.albedo.value(record.u, record.v, &record.point);
+        Color(albedo.0.scale(cosine(record, scattered) / PI))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        core::Vec3,
+        material::{tests::sample_matches_eval_over_pdf, texture::solid::ConstantTexture},
+    };
+
+    #[test]
+    fn sample_matches_eval() {
+        let albedo = Color(Vec3::new(0.2, 0.4, 0.6));
+        let material = Lambertian::new(ConstantTexture::from(albedo));
+        let samples = sample_matches_eval_over_pdf(&material, Vec3::new(0.0, 1.0, 0.0), 100);
+        assert_eq!(samples.len(), 100);
+        assert!(samples.iter().all(|s| !s.specular));
+    }
 }
@@ -1,7 +1,9 @@
+use std::f64::consts::PI;
+
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
-use super::{texture::Texture, Material, Scatter};
+use super::{cosine, texture::Texture, Material, Scatter};
 use crate::{
     core::{math::random_unit_vector, Color, Ray},
     geometry::hittable::HitRecord,
@@ -23,9 +25,13 @@ impl<T: Texture> Material for DiffuseLight<T> {
         Some(self.albedo.value(record.u, record.v, &record.point))
     }
 
-    fn scatter<R: Rng>(&self, _rng: &mut R, _ray: &Ray, _record: &HitRecord) -> Option<Scatter> {
+    fn sample<R: Rng>(&self, _rng: &mut R, _ray: &Ray, _record: &HitRecord) -> Option<Scatter> {
         None
     }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,8 +45,15 @@ impl<T> FairyLight<T> {
     }
 }
 
+impl<T: Texture> FairyLight<T> {
+    fn reflectance(&self, record: &HitRecord) -> Color {
+        let albedo = self.albedo.value(record.u, record.v, &record.point);
+        Color(albedo.0.unit())
+    }
+}
+
 impl<T: Texture> Material for FairyLight<T> {
-    fn scatter<R: Rng>(&self, rng: &mut R, _ray: &Ray, record: &HitRecord) -> Option<Scatter> {
+    fn sample<R: Rng>(&self, rng: &mut R, ray: &Ray, record: &HitRecord) -> Option<Scatter> {
         let mut scatter = record.normal + random_unit_vector(rng);
         if scatter.near_zero() {
             scatter = record.normal;
@@ -48,14 +61,29 @@ impl<T: Texture> Material for FairyLight<T> {
         let direction = Ray {
             orig: record.point,
             direction: scatter,
+            time: ray.time,
         };
-        let attenuation = self.albedo.value(record.u, record.v, &record.point);
+        let pdf = cosine(record, &direction) / PI;
         Some(Scatter {
             direction,
-            attenuation: Color(attenuation.0.unit()),
+            attenuation: self.reflectance(record),
+            pdf,
+            specular: false,
         })
     }
 
+    fn pdf(&self, _ray: &Ray, record: &HitRecord, scattered: &Ray) -> f64 {
+        cosine(record, scattered) / PI
+    }
+
+    fn eval(&self, _ray: &Ray, record: &HitRecord, scattered: &Ray) -> Color {
+        Color(
+            self.reflectance(record)
+                .0
+                .scale(cosine(record, scattered) / PI),
+        )
+    }
+
     fn emitted(&self, ray: &Ray, record: &HitRecord) -> Option<Color> {
         let src_color = self.albedo.value(record.u, record.v, &record.point);
 
@@ -64,4 +92,8 @@ impl<T: Texture> Material for FairyLight<T> {
         Some(Color(src_color.0.scale(scale / ray.direction.length())))
         // Some(src_color)
     }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
 }
@@ -4,10 +4,13 @@ use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 use super::{
+    conductor::RoughConductor,
     dielectric::Dielectric,
+    isotropic::Isotropic,
     lambertian::Lambertian,
     lighting::{DiffuseLight, FairyLight},
     metal::Metal,
+    rough_dielectric::RoughDielectric,
     texture::{
         loader::{LoadableTexture, TextureManager},
         Texture,
@@ -17,13 +20,16 @@ use super::{
 
 pub type SceneMaterial = MaterialType<Arc<dyn Texture + Send + Sync>>;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MaterialType<T> {
     Metal(Metal),
     Dielectric(Dielectric),
     Lambertian(Lambertian<T>),
     DiffuseLight(DiffuseLight<T>),
     FairyLight(FairyLight<T>),
+    Isotropic(Isotropic<T>),
+    RoughConductor(RoughConductor<T>),
+    RoughDielectric(RoughDielectric<T>),
 }
 
 impl<T: LoadableTexture> MaterialType<T> {
@@ -41,6 +47,24 @@ impl<T: LoadableTexture> MaterialType<T> {
                 let t = f.albedo.load_texture(manager)?;
                 MaterialType::FairyLight(FairyLight { albedo: t })
             }
+            MaterialType::Isotropic(i) => {
+                let t = i.albedo.load_texture(manager)?;
+                MaterialType::Isotropic(Isotropic { albedo: t })
+            }
+            MaterialType::RoughConductor(m) => {
+                let t = m.roughness.load_texture(manager)?;
+                MaterialType::RoughConductor(RoughConductor {
+                    ior: m.ior,
+                    roughness: t,
+                })
+            }
+            MaterialType::RoughDielectric(m) => {
+                let t = m.roughness.load_texture(manager)?;
+                MaterialType::RoughDielectric(RoughDielectric {
+                    ir: m.ir,
+                    roughness: t,
+                })
+            }
             MaterialType::Metal(m) => MaterialType::Metal(m),
             MaterialType::Dielectric(m) => MaterialType::Dielectric(m),
         })
@@ -48,18 +72,57 @@ impl<T: LoadableTexture> MaterialType<T> {
 }
 
 impl<T: Texture> Material for MaterialType<T> {
-    fn scatter<R: Rng>(
+    fn sample<R: Rng>(
         &self,
         rng: &mut R,
         ray: &crate::core::Ray,
         record: &crate::geometry::hittable::HitRecord,
     ) -> Option<super::Scatter> {
         match self {
-            MaterialType::Metal(m) => m.scatter(rng, ray, record),
-            MaterialType::Dielectric(m) => m.scatter(rng, ray, record),
-            MaterialType::Lambertian(m) => m.scatter(rng, ray, record),
-            MaterialType::DiffuseLight(m) => m.scatter(rng, ray, record),
-            MaterialType::FairyLight(m) => m.scatter(rng, ray, record),
+            MaterialType::Metal(m) => m.sample(rng, ray, record),
+            MaterialType::Dielectric(m) => m.sample(rng, ray, record),
+            MaterialType::Lambertian(m) => m.sample(rng, ray, record),
+            MaterialType::DiffuseLight(m) => m.sample(rng, ray, record),
+            MaterialType::FairyLight(m) => m.sample(rng, ray, record),
+            MaterialType::Isotropic(m) => m.sample(rng, ray, record),
+            MaterialType::RoughConductor(m) => m.sample(rng, ray, record),
+            MaterialType::RoughDielectric(m) => m.sample(rng, ray, record),
+        }
+    }
+
+    fn pdf(
+        &self,
+        ray: &crate::core::Ray,
+        record: &crate::geometry::hittable::HitRecord,
+        scattered: &crate::core::Ray,
+    ) -> f64 {
+        match self {
+            MaterialType::Metal(m) => m.pdf(ray, record, scattered),
+            MaterialType::Dielectric(m) => m.pdf(ray, record, scattered),
+            MaterialType::Lambertian(m) => m.pdf(ray, record, scattered),
+            MaterialType::DiffuseLight(m) => m.pdf(ray, record, scattered),
+            MaterialType::FairyLight(m) => m.pdf(ray, record, scattered),
+            MaterialType::Isotropic(m) => m.pdf(ray, record, scattered),
+            MaterialType::RoughConductor(m) => m.pdf(ray, record, scattered),
+            MaterialType::RoughDielectric(m) => m.pdf(ray, record, scattered),
+        }
+    }
+
+    fn eval(
+        &self,
+        ray: &crate::core::Ray,
+        record: &crate::geometry::hittable::HitRecord,
+        scattered: &crate::core::Ray,
+    ) -> crate::core::Color {
+        match self {
+            MaterialType::Metal(m) => m.eval(ray, record, scattered),
+            MaterialType::Dielectric(m) => m.eval(ray, record, scattered),
+            MaterialType::Lambertian(m) => m.eval(ray, record, scattered),
+            MaterialType::DiffuseLight(m) => m.eval(ray, record, scattered),
+            MaterialType::FairyLight(m) => m.eval(ray, record, scattered),
+            MaterialType::Isotropic(m) => m.eval(ray, record, scattered),
+            MaterialType::RoughConductor(m) => m.eval(ray, record, scattered),
+            MaterialType::RoughDielectric(m) => m.eval(ray, record, scattered),
         }
     }
 
@@ -74,6 +137,22 @@ impl<T: Texture> Material for MaterialType<T> {
             MaterialType::Lambertian(m) => m.emitted(ray, record),
             MaterialType::DiffuseLight(m) => m.emitted(ray, record),
             MaterialType::FairyLight(m) => m.emitted(ray, record),
+            MaterialType::Isotropic(m) => m.emitted(ray, record),
+            MaterialType::RoughConductor(m) => m.emitted(ray, record),
+            MaterialType::RoughDielectric(m) => m.emitted(ray, record),
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        match self {
+            MaterialType::Metal(m) => m.is_emissive(),
+            MaterialType::Dielectric(m) => m.is_emissive(),
+            MaterialType::Lambertian(m) => m.is_emissive(),
+            MaterialType::DiffuseLight(m) => m.is_emissive(),
+            MaterialType::FairyLight(m) => m.is_emissive(),
+            MaterialType::Isotropic(m) => m.is_emissive(),
+            MaterialType::RoughConductor(m) => m.is_emissive(),
+            MaterialType::RoughDielectric(m) => m.is_emissive(),
         }
     }
 }
@@ -103,3 +182,18 @@ impl<T> From<FairyLight<T>> for MaterialType<T> {
         MaterialType::FairyLight(x)
     }
 }
+impl<T> From<Isotropic<T>> for MaterialType<T> {
+    fn from(x: Isotropic<T>) -> Self {
+        MaterialType::Isotropic(x)
+    }
+}
+impl<T> From<RoughConductor<T>> for MaterialType<T> {
+    fn from(x: RoughConductor<T>) -> Self {
+        MaterialType::RoughConductor(x)
+    }
+}
+impl<T> From<RoughDielectric<T>> for MaterialType<T> {
+    fn from(x: RoughDielectric<T>) -> Self {
+        MaterialType::RoughDielectric(x)
+    }
+}
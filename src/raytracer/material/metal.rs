@@ -24,17 +24,22 @@ impl Metal {
 }
 
 impl Material for Metal {
-    fn scatter<R: Rng>(&self, rng: &mut R, ray: &Ray, record: &HitRecord) -> Option<Scatter> {
+    fn sample<R: Rng>(&self, rng: &mut R, ray: &Ray, record: &HitRecord) -> Option<Scatter> {
         let reflected = ray.direction.unit().reflect(&record.normal);
 
         let direction = Ray {
             orig: record.point,
             direction: reflected + random_in_unit_sphere(rng).scale(self.fuzz),
+            time: ray.time,
         };
 
         Some(Scatter {
             direction,
             attenuation: self.albedo,
+            pdf: 0.0,
+            // the fuzz has no closed form density, so it is treated as a
+            // (blurry) delta lobe
+            specular: true,
         })
     }
 }
@@ -0,0 +1,158 @@
+use std::f64::consts::PI;
+
+use rand::Rng;
+
+use super::texture::Texture;
+use crate::{
+    core::{math::from_local_frame, Color, Vec3},
+    geometry::hittable::HitRecord,
+};
+
+/// Below this the distribution is so sharp the math loses precision.
+const MIN_ALPHA: f64 = 0.001;
+
+/// The GGX (Trowbridge-Reitz) distribution of microfacet normals, around the
+/// shading normal `n`. Every direction here points away from the surface.
+pub(crate) struct Ggx {
+    pub n: Vec3,
+    alpha: f64,
+}
+
+impl Ggx {
+    /// `roughness` is the perceptual roughness, squared to get alpha.
+    pub fn new(n: Vec3, roughness: f64) -> Ggx {
+        let r = roughness.clamp(0.0, 1.0);
+        Ggx {
+            n,
+            alpha: (r * r).max(MIN_ALPHA),
+        }
+    }
+
+    /// Read roughness from a texture, averaging the channels.
+    pub fn from_texture<T: Texture>(record: &HitRecord, roughness: &T) -> Ggx {
+        let c = roughness.value(record.u, record.v, &record.point).0;
+        Ggx::new(record.normal, (c.x() + c.y() + c.z()) / 3.0)
+    }
+
+    pub fn d(&self, h: &Vec3) -> f64 {
+        let cos = self.n.dot(h);
+        if cos <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let denom = cos * cos * (a2 - 1.0) + 1.0;
+        a2 / (PI * denom * denom)
+    }
+
+    fn g1(&self, v: &Vec3, h: &Vec3) -> f64 {
+        let cos = self.n.dot(v);
+        // a facet can't be seen from behind
+        if v.dot(h) * cos <= 0.0 {
+            return 0.0;
+        }
+        let cos = cos.abs();
+        let a2 = self.alpha * self.alpha;
+        2.0 * cos / (cos + (a2 + (1.0 - a2) * cos * cos).sqrt())
+    }
+
+    /// Separable Smith masking-shadowing.
+    pub fn g(&self, wo: &Vec3, wi: &Vec3, h: &Vec3) -> f64 {
+        self.g1(wo, h) * self.g1(wi, h)
+    }
+
+    /// Pick a microfacet normal proportional to `d(h) * cos(h)`.
+    pub fn sample_h<R: Rng>(&self, rng: &mut R) -> Vec3 {
+        let r1 = rng.gen::<f64>();
+        let r2 = rng.gen::<f64>();
+        let a2 = self.alpha * self.alpha;
+        let cos2 = (1.0 - r1) / (r1 * (a2 - 1.0) + 1.0);
+        let cos = cos2.sqrt();
+        let sin = (1.0 - cos2).max(0.0).sqrt();
+        let phi = 2.0 * PI * r2;
+        from_local_frame(&self.n, &Vec3::new(sin * phi.cos(), sin * phi.sin(), cos))
+    }
+
+    /// Density of `sample_h` picking `h`.
+    pub fn pdf_h(&self, h: &Vec3) -> f64 {
+        self.d(h) * self.n.dot(h).abs()
+    }
+}
+
+/// Reflect `v` (pointing away from the surface) about `h`.
+pub(crate) fn reflect(v: &Vec3, h: &Vec3) -> Vec3 {
+    h.scale(2.0 * v.dot(h)) - *v
+}
+
+/// Fresnel reflectance between dielectrics, `eta` is the ratio of the far
+/// side over the side `cos_i` is measured on.
+pub(crate) fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.abs().min(1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    (rs * rs + rp * rp) / 2.0
+}
+
+/// Fresnel reflectance of a conductor with complex index `eta + i k`, per
+/// channel.
+pub(crate) fn fresnel_conductor(cos_i: f64, eta: &Color, k: &Color) -> Color {
+    let c = cos_i.abs().min(1.0);
+    let mut out = Vec3::default();
+    for d in 0..3 {
+        out[d] = fresnel_conductor_channel(c, eta.0[d], k.0[d]);
+    }
+    Color(out)
+}
+
+fn fresnel_conductor_channel(cos: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos * cos;
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let t1 = a2b2 + cos2;
+    let a = (0.5 * (a2b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos * a;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    (rp + rs) / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::core::math::random_unit_vector;
+
+    #[test]
+    fn projected_distribution_integrates_to_one() {
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(0xDEADBEEF);
+        for roughness in &[0.3, 0.6, 1.0] {
+            let ggx = Ggx::new(Vec3::new(0.0, 0.0, 1.0), *roughness);
+            let n = 200_000;
+            let total: f64 = (0..n)
+                .map(|_| ggx.pdf_h(&random_unit_vector(&mut rng)))
+                .sum();
+            let integral = total * 4.0 * PI / n as f64;
+            assert!((integral - 1.0).abs() < 0.05, "integral {}", integral);
+        }
+    }
+
+    #[test]
+    fn fresnel_limits() {
+        // glass at normal incidence reflects about 4%
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-3);
+        // total internal reflection
+        assert_eq!(fresnel_dielectric(0.1, 1.0 / 1.5), 1.0);
+        // a conductor with no absorption matches a dielectric
+        let eta = Color(Vec3::new(1.5, 1.5, 1.5));
+        let f = fresnel_conductor(0.7, &eta, &Color::default());
+        assert!((f.0.x() - fresnel_dielectric(0.7, 1.5)).abs() < 1e-9);
+    }
+}
@@ -1,9 +1,13 @@
+pub mod conductor;
 pub mod dielectric;
+pub mod isotropic;
 pub mod lambertian;
 pub mod lighting;
 pub mod material_type;
 pub mod metal;
+mod microfacet;
 pub mod perlin;
+pub mod rough_dielectric;
 pub mod texture;
 
 use rand::Rng;
@@ -11,15 +15,88 @@ use rand::Rng;
 use super::geometry::hittable::HitRecord;
 use crate::core::{Color, Ray};
 
+/// A direction picked by `Material::sample`.
 #[derive(Debug, Clone)]
 pub struct Scatter {
     pub direction: Ray,
+    /// `eval / pdf`, what the path throughput is multiplied by
     pub attenuation: Color,
+    /// Density of picking `direction`, with respect to solid angle.
+    /// Meaningless for specular samples.
+    pub pdf: f64,
+    /// The direction came from a delta lobe, `pdf` and `eval` can't
+    /// reproduce it so it is never mixed with other strategies.
+    pub specular: bool,
 }
 
 pub trait Material {
-    fn scatter<R: Rng>(&self, rng: &mut R, ray: &Ray, record: &HitRecord) -> Option<Scatter>;
+    /// Pick an outgoing direction for a ray arriving at `record`.
+    fn sample<R: Rng>(&self, rng: &mut R, ray: &Ray, record: &HitRecord) -> Option<Scatter>;
+    /// Density, with respect to solid angle, of `sample` picking `scattered`.
+    /// Always zero for specular materials.
+    fn pdf(&self, _ray: &Ray, _record: &HitRecord, _scattered: &Ray) -> f64 {
+        0.0
+    }
+    /// The BSDF times the cosine of `scattered` with the normal.
+    /// Always black for specular materials.
+    fn eval(&self, _ray: &Ray, _record: &HitRecord, _scattered: &Ray) -> Color {
+        Color::default()
+    }
     fn emitted(&self, _ray: &Ray, _record: &HitRecord) -> Option<Color> {
         None
     }
+    /// Materials that emit are collected into the scene's light list.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+}
+
+/// Cosine of the angle between `scattered` and the normal, zero below the surface.
+pub(crate) fn cosine(record: &HitRecord, scattered: &Ray) -> f64 {
+    record.normal.dot(&scattered.direction.unit()).max(0.0)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::core::{Point, Vec3};
+
+    /// Shoot rays from `origin` at a surface through the origin facing +y,
+    /// and check every sample agrees with `pdf` and `eval`. Returns the
+    /// samples, up to `count` of them, for material specific checks.
+    pub(crate) fn sample_matches_eval_over_pdf<M: Material>(
+        material: &M,
+        origin: Vec3,
+        count: usize,
+    ) -> Vec<Scatter> {
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(0xDEADBEEF);
+        let ray = Ray::new(Point(origin), origin.scale(-1.0));
+        let record = HitRecord::new(
+            &ray,
+            Point(Vec3::default()),
+            Vec3::new(0.0, 1.0, 0.0),
+            1.0,
+            0.0,
+            0.0,
+        );
+        let samples: Vec<Scatter> = (0..count)
+            .filter_map(|_| material.sample(&mut rng, &ray, &record))
+            .collect();
+        for s in &samples {
+            let pdf = material.pdf(&ray, &record, &s.direction);
+            assert!(
+                (pdf - s.pdf).abs() <= 1e-6 * pdf,
+                "pdf {} vs {}",
+                pdf,
+                s.pdf
+            );
+            if pdf > 0.0 {
+                let f = material.eval(&ray, &record, &s.direction);
+                assert!((f.0.scale(1.0 / pdf) - s.attenuation.0).near_zero());
+            }
+        }
+        samples
+    }
 }
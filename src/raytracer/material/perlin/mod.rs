@@ -131,7 +131,7 @@ fn perlin_generate_perm(rng: &mut ThreadRng) -> Vec<usize> {
     p
 }
 
-fn permute<T>(rng: &mut ThreadRng, p: &mut Vec<T>) {
+fn permute<T>(rng: &mut ThreadRng, p: &mut [T]) {
     for idx in (1..p.len()).rev() {
         let target = rng.gen_range(0..idx + 1);
         p.swap(idx, target)
@@ -0,0 +1,137 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    microfacet::{fresnel_dielectric, reflect, Ggx},
+    texture::Texture,
+    Material, Scatter,
+};
+use crate::{
+    core::{Color, Ray, Vec3},
+    geometry::hittable::HitRecord,
+};
+
+/// Frosted glass, a dielectric with GGX microfacet roughness that both
+/// reflects and transmits.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoughDielectric<T> {
+    pub ir: f64,
+    pub roughness: T,
+}
+
+impl<T> RoughDielectric<T> {
+    pub fn new(ir: f64, roughness: T) -> RoughDielectric<T> {
+        RoughDielectric { ir, roughness }
+    }
+}
+
+impl<T: Texture> RoughDielectric<T> {
+    /// Ratio of the index on the far side of the surface over the near side.
+    fn eta(&self, record: &HitRecord) -> f64 {
+        if record.front_face {
+            self.ir
+        } else {
+            1.0 / self.ir
+        }
+    }
+
+    fn eval_pdf(&self, ggx: &Ggx, eta: f64, wo: &Vec3, wi: &Vec3) -> (Color, f64) {
+        let cos_o = ggx.n.dot(wo);
+        let cos_i = ggx.n.dot(wi);
+        if cos_o <= 0.0 || cos_i == 0.0 {
+            return (Color::default(), 0.0);
+        }
+
+        if cos_i > 0.0 {
+            let h = (*wo + *wi).unit();
+            let fresnel = fresnel_dielectric(wo.dot(&h), eta);
+            let f = fresnel * ggx.d(&h) * ggx.g(wo, wi, &h) / (4.0 * cos_o);
+            let pdf = fresnel * ggx.pdf_h(&h) / (4.0 * wo.dot(&h).abs());
+            return (Color(Vec3::new(f, f, f)), pdf);
+        }
+
+        let mut h = (*wo + wi.scale(eta)).unit();
+        if ggx.n.dot(&h) < 0.0 {
+            h.scale_mut(-1.0);
+        }
+        let wo_h = wo.dot(&h);
+        let wi_h = wi.dot(&h);
+        // both sides of the facet have to agree with the macro surface
+        if wo_h <= 0.0 || wi_h >= 0.0 {
+            return (Color::default(), 0.0);
+        }
+        let fresnel = fresnel_dielectric(wo_h, eta);
+        let denom = wo_h + eta * wi_h;
+        let denom2 = denom * denom;
+        let f =
+            (1.0 - fresnel) * ggx.d(&h) * ggx.g(wo, wi, &h) * wi_h.abs() * wo_h / (cos_o * denom2);
+        let pdf = (1.0 - fresnel) * ggx.pdf_h(&h) * eta * eta * wi_h.abs() / denom2;
+        (Color(Vec3::new(f, f, f)), pdf)
+    }
+}
+
+impl<T: Texture> Material for RoughDielectric<T> {
+    fn sample<R: Rng>(&self, rng: &mut R, ray: &Ray, record: &HitRecord) -> Option<Scatter> {
+        let ggx = Ggx::from_texture(record, &self.roughness);
+        let eta = self.eta(record);
+        let wo = ray.direction.unit().scale(-1.0);
+        let h = ggx.sample_h(rng);
+        let wo_h = wo.dot(&h);
+        if wo_h <= 0.0 {
+            return None;
+        }
+
+        let fresnel = fresnel_dielectric(wo_h, eta);
+        let wi = if rng.gen::<f64>() < fresnel {
+            reflect(&wo, &h)
+        } else {
+            let cos_t = (1.0 - (1.0 - wo_h * wo_h) / (eta * eta)).sqrt();
+            h.scale(wo_h / eta - cos_t) - wo.scale(1.0 / eta)
+        };
+
+        let (f, pdf) = self.eval_pdf(&ggx, eta, &wo, &wi);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(Scatter {
+            direction: Ray::with_time(record.point, wi, ray.time),
+            attenuation: Color(f.0.scale(1.0 / pdf)),
+            pdf,
+            specular: false,
+        })
+    }
+
+    fn pdf(&self, ray: &Ray, record: &HitRecord, scattered: &Ray) -> f64 {
+        let ggx = Ggx::from_texture(record, &self.roughness);
+        let wo = ray.direction.unit().scale(-1.0);
+        let wi = scattered.direction.unit();
+        self.eval_pdf(&ggx, self.eta(record), &wo, &wi).1
+    }
+
+    fn eval(&self, ray: &Ray, record: &HitRecord, scattered: &Ray) -> Color {
+        let ggx = Ggx::from_texture(record, &self.roughness);
+        let wo = ray.direction.unit().scale(-1.0);
+        let wi = scattered.direction.unit();
+        self.eval_pdf(&ggx, self.eta(record), &wo, &wi).0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::{tests::sample_matches_eval_over_pdf, texture::solid::ConstantTexture};
+
+    #[test]
+    fn sample_matches_eval() {
+        let material =
+            RoughDielectric::new(1.5, ConstantTexture::from(Color(Vec3::new(0.3, 0.3, 0.3))));
+        for origin in &[Vec3::new(1.0, 1.0, 0.0), Vec3::new(0.3, -1.0, 0.0)] {
+            let samples = sample_matches_eval_over_pdf(&material, *origin, 200);
+            let transmitted = samples
+                .iter()
+                .filter(|s| s.direction.direction.dot(origin) < 0.0)
+                .count();
+            assert!(transmitted > 100, "transmitted {}", transmitted);
+        }
+    }
+}
@@ -1,9 +1,11 @@
+use std::str::FromStr;
+
 use rand::Rng;
 
 use crate::{
     bvh::bbox_tree::BboxTreeWorkspace,
     camera::{Camera, CameraPosition},
-    core::{Color, Ray},
+    core::{Color, Ray, Vec3},
     material::Material,
     scene::Scene,
 };
@@ -12,6 +14,19 @@ pub struct Frame<'a> {
     pub camera: &'a Camera,
     pub pos: &'a CameraPosition,
     pub scene: &'a Scene,
+    /// Record traversal work instead of radiance, see `render_heatmap_scanline`
+    pub heatmap: Option<HeatmapMetric>,
+}
+
+/// Balance two sampling strategies by the square of their densities.
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+    if a + b == 0.0 {
+        0.0
+    } else {
+        a / (a + b)
+    }
 }
 
 fn ray_color<R: Rng>(
@@ -27,17 +42,51 @@ fn ray_color<R: Rng>(
 
     let mut workspace = scene.workspace_scene(hit_stack);
 
+    // camera rays, and rays leaving a specular surface, could not have been
+    // found by sampling a light so they keep all of the emission they find
+    let mut specular_bounce = true;
+    let mut scatter_pdf = 0.0;
+
     while max_depth > 0 {
-        if let Some((obj, r)) = workspace.hit_workspace(&ray, 0.001, std::f64::INFINITY) {
-            if let Some(e) = obj.material.emitted(&ray, &r) {
-                emitted += Color(attenuation.0 * e.0);
+        let surface = workspace.hit_workspace(&ray, 0.001, f64::INFINITY);
+        let t_surface = surface.as_ref().map(|(_, r)| r.t).unwrap_or(f64::INFINITY);
+        let hit = workspace
+            .sample_medium(rng, &ray, 0.001, t_surface)
+            .or(surface);
+        if let Some((material, r)) = hit {
+            if let Some(e) = material.emitted(&ray, &r) {
+                let weight = if specular_bounce {
+                    1.0
+                } else {
+                    let light_pdf = workspace.light_pdf(&ray.orig, &ray.direction);
+                    power_heuristic(scatter_pdf, light_pdf)
+                };
+                emitted += Color(attenuation.0 * e.0.scale(weight));
             }
-            if let Some(scatter) = obj.material.scatter(rng, &ray, &r) {
-                attenuation = Color(attenuation.0 * scatter.attenuation.0);
-                ray = scatter.direction;
-            } else {
-                break;
+            let scatter = match material.sample(rng, &ray, &r) {
+                Some(scatter) => scatter,
+                None => break,
+            };
+
+            if !scatter.specular {
+                if let Some(direction) = workspace.sample_light(rng, &r.point) {
+                    let shadow = Ray::with_time(r.point, direction, ray.time);
+                    let light_pdf = workspace.light_pdf(&r.point, &direction);
+                    let material_pdf = material.pdf(&ray, &r, &shadow);
+                    if light_pdf > 0.0 && material_pdf > 0.0 {
+                        if let Some(e) = workspace.light_emission(rng, &shadow) {
+                            let f = material.eval(&ray, &r, &shadow);
+                            let weight = power_heuristic(light_pdf, material_pdf) / light_pdf;
+                            emitted += Color((attenuation.0 * f.0 * e.0).scale(weight));
+                        }
+                    }
+                }
             }
+            specular_bounce = scatter.specular;
+            scatter_pdf = scatter.pdf;
+
+            attenuation = Color(attenuation.0 * scatter.attenuation.0);
+            ray = scatter.direction;
         } else {
             emitted += Color(attenuation.0 * scene.skybox.background(&ray).0);
             break;
@@ -55,6 +104,11 @@ pub fn render_scanline<R: Rng>(
     line_idx: usize,
     buf: &mut [Color],
 ) {
+    if let Some(metric) = frame.heatmap {
+        return render_heatmap_scanline(
+            frame, rng, samples, max_depth, metric, hit_stack, line_idx, buf,
+        );
+    }
     for (idx, buf_c) in buf.iter_mut().enumerate() {
         let mut c = Color::default();
         for _ in 0..samples {
@@ -68,3 +122,127 @@ pub fn render_scanline<R: Rng>(
         *buf_c = c
     }
 }
+
+/// Which traversal counter a heatmap render shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeatmapMetric {
+    /// BVH nodes tested
+    Nodes,
+    /// Primitives intersected
+    Primitives,
+}
+
+impl FromStr for HeatmapMetric {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nodes" => Ok(HeatmapMetric::Nodes),
+            "primitives" => Ok(HeatmapMetric::Primitives),
+            _ => anyhow::bail!(
+                "unknown heatmap metric {:?}, expected nodes or primitives",
+                s
+            ),
+        }
+    }
+}
+
+/// Like `render_scanline`, but each pixel gets the traversal work done for
+/// its whole paths, summed over the samples, in every channel. `hit_stack`
+/// needs to have been made `with_counters`.
+///
+/// The trees inside meshes and instances are counted along with the
+/// scene's own, and every unbounded object tested counts as a primitive.
+#[allow(clippy::too_many_arguments)]
+fn render_heatmap_scanline<R: Rng>(
+    frame: &Frame<'_>,
+    rng: &mut R,
+    samples: usize,
+    max_depth: usize,
+    metric: HeatmapMetric,
+    hit_stack: &mut BboxTreeWorkspace,
+    line_idx: usize,
+    buf: &mut [Color],
+) {
+    for (idx, buf_c) in buf.iter_mut().enumerate() {
+        hit_stack.reset_counters();
+        for _ in 0..samples {
+            let jitter_idx = idx as f64 + rng.gen::<f64>();
+            let jitter_line_idx = line_idx as f64 + rng.gen::<f64>();
+            let r = frame
+                .camera
+                .pixel_ray(rng, frame.pos, jitter_idx, jitter_line_idx);
+            ray_color(rng, hit_stack, &r, frame.scene, max_depth);
+        }
+        let counters = hit_stack.counters().unwrap_or_default();
+        let count = match metric {
+            HeatmapMetric::Nodes => counters.nodes,
+            HeatmapMetric::Primitives => counters.primitives,
+        } as f64;
+        *buf_c = Color(Vec3::new(count, count, count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{
+        core::Point,
+        geometry::{disk::Disk, plane::Plane, sphere::Sphere},
+        material::{
+            lambertian::Lambertian, lighting::DiffuseLight, texture::loader::TextureLoader,
+        },
+        scene::SceneBuilder,
+        skybox::SkyBox,
+    };
+
+    #[test]
+    fn unsampled_lights_keep_full_weight() {
+        // a floor under a huge emissive disk, which can't be sampled, so
+        // paths only find it by bouncing off the floor. The light under the
+        // floor can't reach it, it just keeps the light list from being empty
+        let mut scene = SceneBuilder::default();
+        scene.set_skybox(SkyBox::None);
+        scene.add(
+            Plane {
+                point: Point(Vec3::default()),
+                normal: Vec3::new(0.0, 1.0, 0.0),
+                tile_size: 1.0,
+            },
+            Lambertian::new(TextureLoader::solid(0.5, 0.5, 0.5)),
+        );
+        scene.add(
+            Disk::new(
+                Point(Vec3::new(0.0, 1.0, 0.0)),
+                Vec3::new(0.0, -1.0, 0.0),
+                1000.0,
+            ),
+            DiffuseLight::new(TextureLoader::solid(1.0, 1.0, 1.0)),
+        );
+        scene.add(
+            Sphere {
+                center: Point(Vec3::new(0.0, -5.0, 0.0)),
+                radius: 1.0,
+            },
+            DiffuseLight::new(TextureLoader::solid(1.0, 1.0, 1.0)),
+        );
+        let scene = scene.finalize().unwrap();
+        assert!(scene
+            .workspace_scene(&mut BboxTreeWorkspace::default())
+            .has_lights());
+
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(0xDEADBEEF);
+        let mut hit_stack = BboxTreeWorkspace::default();
+        let ray = Ray::new(Point(Vec3::new(0.0, 0.5, 0.0)), Vec3::new(0.0, -1.0, 0.0));
+        let n = 2000;
+        let total: f64 = (0..n)
+            .map(|_| ray_color(&mut rng, &mut hit_stack, &ray, &scene, 4).0.x())
+            .sum();
+        // the disk covers nearly all of the floor's sky, so with full weight
+        // the floor reflects its albedo
+        let mean = total / n as f64;
+        assert!((mean - 0.5).abs() < 0.01, "mean {}", mean);
+    }
+}
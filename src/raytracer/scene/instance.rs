@@ -0,0 +1,511 @@
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    lights::{LightList, LightShape},
+    SceneLoadObject, SceneObject,
+};
+use crate::{
+    bvh::{
+        aabb::Aabb,
+        bbox_tree::{BboxTree, BuildOptions, TraversalCounters},
+    },
+    core::Ray,
+    geometry::{
+        hittable::{Geometry, HitRecord},
+        object::GeometricObject,
+        transform::Transform,
+    },
+    material::{
+        material_type::{MaterialType, SceneMaterial},
+        texture::loader::{TextureLoader, TextureManager},
+        Material,
+    },
+};
+
+/// Handle to a prototype registered with `SceneBuilder::add_prototype`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrototypeId(pub usize);
+
+/// A group of objects that is built into its own tree once, and placed in
+/// the scene any number of times by instances.
+#[derive(Default, Serialize, Deserialize)]
+pub struct PrototypeBuilder {
+    objects: Vec<SceneLoadObject>,
+}
+
+impl PrototypeBuilder {
+    pub fn add<G: Into<GeometricObject>, M: Into<MaterialType<TextureLoader>>>(
+        &mut self,
+        g: G,
+        m: M,
+    ) -> &mut Self {
+        self.objects.push(SceneLoadObject {
+            geometry: g.into(),
+            material: m.into(),
+        });
+        self
+    }
+
+    pub(super) fn finalize(
+        self,
+        textures: &mut TextureManager,
+        options: &BuildOptions,
+    ) -> anyhow::Result<Prototype> {
+        let mut objects = Vec::with_capacity(self.objects.len());
+        for load_obj in self.objects {
+            if load_obj.geometry.bounding_box().is_none() {
+                anyhow::bail!("unbounded geometry can not be part of a prototype");
+            }
+            if load_obj.geometry.contains_medium() {
+                anyhow::bail!("media can not be part of a prototype");
+            }
+            let material = load_obj.material.load_texture(textures)?;
+            objects.push(SceneObject {
+                geometry: load_obj.geometry,
+                material,
+            });
+        }
+        Ok(Prototype {
+            tree: BboxTree::with_options(objects, options),
+        })
+    }
+}
+
+/// The bottom level tree shared by every instance of a prototype.
+pub struct Prototype {
+    tree: BboxTree<SceneObject>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct InstanceLoadObject {
+    pub prototype: PrototypeId,
+    #[serde(default)]
+    pub transform: Transform,
+    /// Replaces the material of every object in the prototype
+    #[serde(default)]
+    pub material: Option<MaterialType<TextureLoader>>,
+}
+
+pub struct SceneInstance {
+    prototype: Arc<Prototype>,
+    transform: Transform,
+    material: Option<SceneMaterial>,
+    bbox: Option<Aabb>,
+}
+
+impl SceneInstance {
+    pub(super) fn new(
+        prototype: Arc<Prototype>,
+        transform: Transform,
+        material: Option<SceneMaterial>,
+    ) -> SceneInstance {
+        let bbox = prototype.tree.bounding_box().map(|b| transform.bbox(&b));
+        SceneInstance {
+            prototype,
+            transform,
+            material,
+            bbox,
+        }
+    }
+
+    /// Intersect the prototype in object space, reporting the material of
+    /// the object that was hit, unless this instance overrides it.
+    pub fn hit_material(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> Option<(&SceneMaterial, HitRecord)> {
+        let local = self.transform.inverse().ray(ray);
+        let (obj, mut record) = self.prototype.tree.hit_with(
+            &local,
+            t_min,
+            t_max,
+            counters,
+            |obj, ray, t_min, t_max, counters| {
+                obj.hit_counted(ray, t_min, t_max, counters)
+                    .map(|hit| (obj, hit))
+            },
+        )?;
+        record.point = self.transform.point(&record.point);
+        record.normal = self.transform.normal(&record.normal);
+        let material = self.material.as_ref().unwrap_or(&obj.material);
+        Some((material, record))
+    }
+
+    /// Add the emissive objects of the prototype to `lights`, placed by this
+    /// instance. Returns how many of them could not be sampled.
+    pub(super) fn push_lights(&self, lights: &mut LightList) -> usize {
+        let mut unsampled = 0;
+        for obj in self.prototype.tree.items() {
+            let material = self.material.as_ref().unwrap_or(&obj.material);
+            if !material.is_emissive() {
+                continue;
+            }
+            match LightShape::from_geometry(&obj.geometry) {
+                Some(light) => lights.push(
+                    LightShape::Transformed(Box::new(light), self.transform),
+                    material.clone(),
+                ),
+                None => unsampled += 1,
+            }
+        }
+        unsampled
+    }
+}
+
+impl Geometry for SceneInstance {
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        self.hit_counted(ray, t_min, t_max, &mut None)
+    }
+
+    fn bounding_box(&self) -> Option<Aabb> {
+        self.bbox.clone()
+    }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.occluded_counted(ray, t_min, t_max, &mut None)
+    }
+
+    fn hit_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> Option<HitRecord> {
+        self.hit_material(ray, t_min, t_max, counters)
+            .map(|(_, record)| record)
+    }
+
+    fn occluded_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> bool {
+        let local = self.transform.inverse().ray(ray);
+        self.prototype.tree.occluded_with(
+            &local,
+            t_min,
+            t_max,
+            counters,
+            |obj: &SceneObject, ray, t_min, t_max, counters| {
+                obj.occluded_counted(ray, t_min, t_max, counters)
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bvh::bbox_tree::BboxTreeWorkspace,
+        core::{Color, Point, Vec3},
+        geometry::{
+            csg::Csg,
+            hittable::Hittable,
+            plane::Plane,
+            sphere::Sphere,
+            triangle::{MeshData, TriangleMesh},
+        },
+        material::{lambertian::Lambertian, lighting::DiffuseLight, metal::Metal},
+        scene::SceneBuilder,
+    };
+
+    fn scene_with_two_instances() -> SceneBuilder {
+        let mut prototype = PrototypeBuilder::default();
+        prototype.add(
+            Sphere {
+                center: Point(Vec3::default()),
+                radius: 1.0,
+            },
+            Lambertian::new(TextureLoader::solid(0.5, 0.5, 0.5)),
+        );
+        let mut scene = SceneBuilder::default();
+        let id = scene.add_prototype(prototype);
+        scene.add_instance::<Metal>(id, Transform::translate(Vec3::new(0.0, 0.0, -5.0)), None);
+        scene.add_instance(
+            id,
+            Transform::translate(Vec3::new(5.0, 0.0, 0.0)),
+            Some(Metal::new(Color::ones(), None)),
+        );
+        scene
+    }
+
+    #[test]
+    fn hit_instances() {
+        let scene = scene_with_two_instances().finalize().unwrap();
+        let origin = Point(Vec3::default());
+
+        let r = Ray::new(origin, Vec3::new(0.0, 0.0, -1.0));
+        let (material, hit) = scene.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-9);
+        assert!(matches!(material, MaterialType::Lambertian(_)));
+
+        let r = Ray::new(origin, Vec3::new(1.0, 0.0, 0.0));
+        let (material, hit) = scene.hit(&r, 0.0, f64::MAX).unwrap();
+        assert!((hit.point.0 - Vec3::new(4.0, 0.0, 0.0)).near_zero());
+        assert!(matches!(material, MaterialType::Metal(_)));
+
+        let r = Ray::new(origin, Vec3::new(0.0, 1.0, 0.0));
+        assert!(scene.hit(&r, 0.0, f64::MAX).is_none());
+    }
+
+    #[test]
+    fn occluded_by_instances() {
+        let scene = scene_with_two_instances().finalize().unwrap();
+        let mut stack = crate::bvh::bbox_tree::BboxTreeWorkspace::default();
+        let mut workspace = scene.workspace_scene(&mut stack);
+        let origin = Point(Vec3::default());
+
+        let r = Ray::new(origin, Vec3::new(0.0, 0.0, -1.0));
+        assert!(workspace.occluded(&r, 0.0, f64::MAX));
+        // stops short of the instance
+        assert!(!workspace.occluded(&r, 0.0, 3.9));
+
+        let r = Ray::new(origin, Vec3::new(0.0, 1.0, 0.0));
+        assert!(!workspace.occluded(&r, 0.0, f64::MAX));
+    }
+
+    #[test]
+    fn prototype_uses_build_options() {
+        let mut prototype = PrototypeBuilder::default();
+        for i in 0..4 {
+            prototype.add(
+                Sphere {
+                    center: Point(Vec3::new(3.0 * i as f64, 0.0, 0.0)),
+                    radius: 1.0,
+                },
+                Lambertian::new(TextureLoader::solid(0.5, 0.5, 0.5)),
+            );
+        }
+        let options = BuildOptions {
+            max_leaf_size: 1,
+            wide: true,
+            ..BuildOptions::default()
+        };
+        let prototype = prototype
+            .finalize(&mut TextureManager::default(), &options)
+            .unwrap();
+        assert!(prototype.tree.is_wide());
+        assert_eq!(prototype.tree.stats().leaves, 4);
+    }
+
+    #[test]
+    fn instanced_lights_are_sampled() {
+        let mut lamp = PrototypeBuilder::default();
+        lamp.add(
+            Sphere {
+                center: Point(Vec3::default()),
+                radius: 1.0,
+            },
+            DiffuseLight::new(TextureLoader::solid(1.0, 1.0, 1.0)),
+        );
+        let mut scene = SceneBuilder::default();
+        let lamp = scene.add_prototype(lamp);
+        let empty = scene.add_prototype(PrototypeBuilder::default());
+        scene.add_instance::<Metal>(lamp, Transform::translate(Vec3::new(0.0, 0.0, -5.0)), None);
+        scene.add_instance::<Metal>(empty, Transform::identity(), None);
+        let scene = scene.finalize().unwrap();
+
+        let mut stack = BboxTreeWorkspace::default();
+        let ws = scene.workspace_scene(&mut stack);
+        assert!(ws.has_lights());
+        let origin = Point(Vec3::default());
+        assert!(ws.light_pdf(&origin, &Vec3::new(0.0, 0.0, -1.0)) > 0.0);
+        assert_eq!(ws.light_pdf(&origin, &Vec3::new(0.0, 0.0, 1.0)), 0.0);
+    }
+
+    #[test]
+    fn emissive_override_is_sampled() {
+        let mut scene = scene_with_two_instances();
+        let id = PrototypeId(0);
+        scene.add_instance(
+            id,
+            Transform::translate(Vec3::new(0.0, 5.0, 0.0)),
+            Some(DiffuseLight::new(TextureLoader::solid(1.0, 1.0, 1.0))),
+        );
+        let scene = scene.finalize().unwrap();
+
+        let mut stack = BboxTreeWorkspace::default();
+        let ws = scene.workspace_scene(&mut stack);
+        let origin = Point(Vec3::default());
+        assert!(ws.light_pdf(&origin, &Vec3::new(0.0, 1.0, 0.0)) > 0.0);
+        // the other instances keep their own, non emissive, material
+        assert_eq!(ws.light_pdf(&origin, &Vec3::new(0.0, 0.0, -1.0)), 0.0);
+    }
+
+    #[test]
+    fn missing_prototype() {
+        let mut scene = SceneBuilder::default();
+        scene.add_instance::<Metal>(PrototypeId(3), Transform::identity(), None);
+        assert!(scene.finalize().is_err());
+    }
+
+    #[test]
+    fn serde_round_trip() {
+        let json = serde_json::to_string(&scene_with_two_instances()).unwrap();
+        let loaded: SceneBuilder = serde_json::from_str(&json).unwrap();
+        let scene = loaded.finalize().unwrap();
+        let r = Ray::new(Point(Vec3::default()), Vec3::new(1.0, 0.0, 0.0));
+        assert!(scene.hit(&r, 0.0, f64::MAX).is_some());
+    }
+
+    #[test]
+    fn counts_prototype_trees_and_unbounded_objects() {
+        let grey = || Lambertian::new(TextureLoader::solid(0.5, 0.5, 0.5));
+        let mut prototype = PrototypeBuilder::default();
+        for i in 0..8 {
+            prototype.add(
+                Sphere {
+                    center: Point(Vec3::new(3.0 * i as f64, 0.0, 0.0)),
+                    radius: 1.0,
+                },
+                grey(),
+            );
+        }
+        let mut scene = SceneBuilder::default();
+        scene.add(
+            Plane {
+                point: Point(Vec3::new(0.0, -10.0, 0.0)),
+                normal: Vec3::new(0.0, 1.0, 0.0),
+                tile_size: 1.0,
+            },
+            grey(),
+        );
+        let id = scene.add_prototype(prototype);
+        scene.add_instance::<Metal>(id, Transform::identity(), None);
+        let options = BuildOptions {
+            max_leaf_size: 1,
+            ..BuildOptions::default()
+        };
+        let scene = scene.finalize_with(&options).unwrap();
+        let top_nodes = scene.instance_tree().stats().nodes as u64;
+
+        let mut stack = BboxTreeWorkspace::with_counters();
+        let r = Ray::new(Point(Vec3::new(0.0, 5.0, 0.0)), Vec3::new(0.0, -1.0, 0.0));
+        let (_, hit) = scene
+            .workspace_scene(&mut stack)
+            .hit_workspace(&r, 0.0, f64::MAX)
+            .unwrap();
+        assert!((hit.t - 4.0).abs() < 1e-9);
+        let counters = stack.counters().unwrap();
+        assert!(counters.nodes > top_nodes, "{:?}", counters);
+        // the plane, the instance and the sphere inside it
+        assert!(counters.primitives >= 3, "{:?}", counters);
+
+        stack.reset_counters();
+        assert!(scene.workspace_scene(&mut stack).occluded(&r, 0.0, 4.5));
+        let counters = stack.counters().unwrap();
+        assert!(counters.nodes > top_nodes, "{:?}", counters);
+        assert!(counters.primitives >= 2, "{:?}", counters);
+    }
+
+    #[test]
+    fn counts_meshes_inside_csg_for_shadow_rays() {
+        // a strip of quads along x, with a sphere carved out past its end
+        let mut mesh = MeshData {
+            vertices: vec![],
+            normals: vec![],
+            uvs: vec![],
+            faces: vec![],
+        };
+        for i in 0..16 {
+            let x = i as f64;
+            let first = mesh.vertices.len();
+            for (dx, dz) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+                mesh.vertices.push(Point(Vec3::new(x + dx, 0.0, dz)));
+            }
+            mesh.faces.push([first, first + 1, first + 2]);
+            mesh.faces.push([first, first + 2, first + 3]);
+        }
+        let csg = Csg::difference(
+            TriangleMesh::new(mesh).unwrap(),
+            Sphere {
+                center: Point(Vec3::new(40.0, 0.0, 0.0)),
+                radius: 1.0,
+            },
+        );
+        let mut scene = SceneBuilder::default();
+        scene.add(csg, Lambertian::new(TextureLoader::solid(0.5, 0.5, 0.5)));
+        let scene = scene.finalize().unwrap();
+        let top_nodes = scene.object_tree().stats().nodes as u64;
+
+        let mut stack = BboxTreeWorkspace::with_counters();
+        let r = Ray::new(Point(Vec3::new(0.5, 5.0, 0.5)), Vec3::new(0.0, -1.0, 0.0));
+        assert!(scene.workspace_scene(&mut stack).occluded(&r, 0.0, 10.0));
+        let counters = stack.counters().unwrap();
+        assert!(counters.nodes > top_nodes, "{:?}", counters);
+        // the csg and at least one triangle of the mesh
+        assert!(counters.primitives >= 2, "{:?}", counters);
+    }
+
+    #[test]
+    fn wide_scene_matches_binary() {
+        use rand::{Rng, SeedableRng};
+
+        let build = |wide: bool| {
+            let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(5);
+            let sphere = |rng: &mut rand_chacha::ChaCha20Rng, range: f64| Sphere {
+                center: Point(Vec3::random_range_with_rng(rng, -range, range)),
+                radius: rng.gen_range(0.1..0.8),
+            };
+            let grey = || Lambertian::new(TextureLoader::solid(0.5, 0.5, 0.5));
+            let mut scene = SceneBuilder::default();
+            for _ in 0..200 {
+                scene.add(sphere(&mut rng, 10.0), grey());
+            }
+            let mut prototype = PrototypeBuilder::default();
+            for _ in 0..8 {
+                prototype.add(sphere(&mut rng, 1.0), grey());
+            }
+            let id = scene.add_prototype(prototype);
+            for _ in 0..20 {
+                let offset = Vec3::random_range_with_rng(&mut rng, -10.0, 10.0);
+                scene.add_instance::<Metal>(id, Transform::translate(offset), None);
+            }
+            let options = BuildOptions {
+                wide,
+                ..BuildOptions::default()
+            };
+            scene.finalize_with(&options).unwrap()
+        };
+        let binary = build(false);
+        let wide = build(true);
+        assert!(wide.object_tree().is_wide() && wide.instance_tree().is_wide());
+        assert!(!binary.object_tree().is_wide());
+
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(6);
+        let mut binary_stack = BboxTreeWorkspace::default();
+        let mut wide_stack = BboxTreeWorkspace::default();
+        let mut binary_ws = binary.workspace_scene(&mut binary_stack);
+        let mut wide_ws = wide.workspace_scene(&mut wide_stack);
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let orig = Point(Vec3::random_range_with_rng(&mut rng, -12.0, 12.0));
+            let dir = Vec3::random_range_with_rng(&mut rng, -1.0, 1.0);
+            let r = Ray::new(orig, dir);
+            let expected = binary_ws
+                .hit_workspace(&r, 0.001, f64::MAX)
+                .map(|(_, hit)| (hit.t, hit.point));
+            let got = wide_ws
+                .hit_workspace(&r, 0.001, f64::MAX)
+                .map(|(_, hit)| (hit.t, hit.point));
+            assert_eq!(expected, got);
+            hits += expected.is_some() as usize;
+
+            let t_max = rng.gen_range(0.0..20.0);
+            assert_eq!(
+                binary_ws.occluded(&r, 0.001, t_max),
+                wide_ws.occluded(&r, 0.001, t_max)
+            );
+        }
+        assert!(hits > 200, "only {} hits", hits);
+    }
+}
@@ -0,0 +1,246 @@
+use rand::Rng;
+
+use crate::{
+    core::{Point, Ray, Vec3},
+    geometry::{
+        hittable::{Geometry, HitRecord, SolidAngleSampler},
+        object::GeometricObject,
+        quad::Quad,
+        rect::{RectXY, RectXZ, RectYZ},
+        sphere::Sphere,
+        transform::Transform,
+    },
+    material::material_type::SceneMaterial,
+};
+
+/// The shapes that can be sampled directly, copied out of the emissive
+/// objects of a scene.
+pub enum LightShape {
+    RectXY(RectXY),
+    RectYZ(RectYZ),
+    RectXZ(RectXZ),
+    Sphere(Sphere),
+    Quad(Quad),
+    /// A sampleable shape placed by a `Transformed`
+    Transformed(Box<LightShape>, Transform),
+}
+
+impl LightShape {
+    /// The sampleable shape of `geometry`, if it has one.
+    pub fn from_geometry(geometry: &GeometricObject) -> Option<LightShape> {
+        match geometry {
+            GeometricObject::RectXY(r) => Some(LightShape::RectXY(r.clone())),
+            GeometricObject::RectYZ(r) => Some(LightShape::RectYZ(r.clone())),
+            GeometricObject::RectXZ(r) => Some(LightShape::RectXZ(r.clone())),
+            GeometricObject::Sphere(s) => Some(LightShape::Sphere(s.clone())),
+            GeometricObject::Quad(q) => Some(LightShape::Quad(q.clone())),
+            GeometricObject::Transformed(t) => LightShape::from_geometry(t.inner())
+                .map(|inner| LightShape::Transformed(Box::new(inner), *t.transform())),
+            _ => None,
+        }
+    }
+
+    fn sample_direction<R: Rng>(&self, rng: &mut R, origin: &Point) -> Vec3 {
+        match self {
+            LightShape::RectXY(r) => r.sample_direction(rng, origin),
+            LightShape::RectYZ(r) => r.sample_direction(rng, origin),
+            LightShape::RectXZ(r) => r.sample_direction(rng, origin),
+            LightShape::Sphere(s) => s.sample_direction(rng, origin),
+            LightShape::Quad(q) => q.sample_direction(rng, origin),
+            LightShape::Transformed(inner, transform) => {
+                let local = transform.inverse().point(origin);
+                transform.vector(&inner.sample_direction(rng, &local))
+            }
+        }
+    }
+
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+        match self {
+            LightShape::RectXY(r) => r.hit(ray, t_min, t_max),
+            LightShape::RectYZ(r) => r.hit(ray, t_min, t_max),
+            LightShape::RectXZ(r) => r.hit(ray, t_min, t_max),
+            LightShape::Sphere(s) => s.hit(ray, t_min, t_max),
+            LightShape::Quad(q) => q.hit(ray, t_min, t_max),
+            LightShape::Transformed(inner, transform) => {
+                let local = transform.inverse().ray(ray);
+                let mut record = inner.hit(&local, t_min, t_max)?;
+                record.point = transform.point(&record.point);
+                record.normal = transform.normal(&record.normal);
+                Some(record)
+            }
+        }
+    }
+
+    fn direction_pdf(&self, origin: &Point, direction: &Vec3) -> f64 {
+        match self {
+            LightShape::RectXY(r) => r.direction_pdf(origin, direction),
+            LightShape::RectYZ(r) => r.direction_pdf(origin, direction),
+            LightShape::RectXZ(r) => r.direction_pdf(origin, direction),
+            LightShape::Sphere(s) => s.direction_pdf(origin, direction),
+            LightShape::Quad(q) => q.direction_pdf(origin, direction),
+            LightShape::Transformed(inner, transform) => {
+                let inverse = transform.inverse();
+                let local = inverse.vector(direction).unit();
+                let pdf = inner.direction_pdf(&inverse.point(origin), &local);
+                // a linear map `m` stretches solid angle around the unit
+                // direction `u` by |det m| / |m u|^3
+                let det = transform
+                    .vector(&Vec3::new(1.0, 0.0, 0.0))
+                    .dot(
+                        &transform
+                            .vector(&Vec3::new(0.0, 1.0, 0.0))
+                            .cross(&transform.vector(&Vec3::new(0.0, 0.0, 1.0))),
+                    )
+                    .abs();
+                pdf * transform.vector(&local).length().powi(3) / det
+            }
+        }
+    }
+}
+
+/// Every light in the scene, sampled as a uniform mixture.
+#[derive(Default)]
+pub struct LightList {
+    lights: Vec<(LightShape, SceneMaterial)>,
+}
+
+impl LightList {
+    pub fn push(&mut self, light: LightShape, material: SceneMaterial) {
+        self.lights.push((light, material))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// Pick a light uniformly and a direction toward it.
+    pub fn sample_direction<R: Rng>(&self, rng: &mut R, origin: &Point) -> Option<Vec3> {
+        if self.lights.is_empty() {
+            return None;
+        }
+        let idx = rng.gen_range(0..self.lights.len());
+        Some(self.lights[idx].0.sample_direction(rng, origin))
+    }
+
+    /// Density of `sample_direction` picking `direction`, counting every
+    /// light that could have produced it.
+    pub fn direction_pdf(&self, origin: &Point, direction: &Vec3) -> f64 {
+        if self.lights.is_empty() {
+            return 0.0;
+        }
+        let total: f64 = self
+            .lights
+            .iter()
+            .map(|(l, _)| l.direction_pdf(origin, direction))
+            .sum();
+        total / self.lights.len() as f64
+    }
+
+    /// The closest light along the ray, with its material.
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(&SceneMaterial, HitRecord)> {
+        let mut closest: Option<(&SceneMaterial, HitRecord)> = None;
+        for (light, material) in &self.lights {
+            let t_closest = closest.as_ref().map(|(_, r)| r.t).unwrap_or(t_max);
+            if let Some(hit) = light.hit(ray, t_min, t_closest) {
+                closest = Some((material, hit))
+            }
+        }
+        closest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{
+        geometry::{rect::xz_rect, transform::Transformed},
+        material::{
+            lighting::DiffuseLight,
+            material_type::MaterialType,
+            texture::loader::{TextureLoader, TextureManager},
+        },
+    };
+
+    fn white_light() -> SceneMaterial {
+        MaterialType::from(DiffuseLight::new(TextureLoader::solid(1.0, 1.0, 1.0)))
+            .load_texture(&mut TextureManager::default())
+            .unwrap()
+    }
+
+    #[test]
+    fn sphere_samples_hit_the_sphere() {
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(0xDEADBEEF);
+        let mut lights = LightList::default();
+        lights.push(
+            LightShape::Sphere(Sphere {
+                center: Point(Vec3::new(0.0, 0.0, -5.0)),
+                radius: 1.0,
+            }),
+            white_light(),
+        );
+        let origin = Point(Vec3::default());
+        for _ in 0..100 {
+            let d = lights.sample_direction(&mut rng, &origin).unwrap();
+            assert!(lights.direction_pdf(&origin, &d) > 0.0);
+        }
+        assert_eq!(
+            lights.direction_pdf(&origin, &Vec3::new(0.0, 0.0, 1.0)),
+            0.0
+        );
+    }
+
+    #[test]
+    fn rect_pdf_integrates_to_one() {
+        // estimate the integral of the pdf over the sphere of directions
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(0xDEADBEEF);
+        let mut lights = LightList::default();
+        lights.push(
+            LightShape::RectXZ(xz_rect(-1.0, 1.0, -1.0, 1.0, 2.0)),
+            white_light(),
+        );
+        let origin = Point(Vec3::default());
+        let n = 200_000;
+        let total: f64 = (0..n)
+            .map(|_| {
+                let d = crate::core::math::random_unit_vector(&mut rng);
+                lights.direction_pdf(&origin, &d)
+            })
+            .sum();
+        let integral = total * 4.0 * std::f64::consts::PI / n as f64;
+        assert!((integral - 1.0).abs() < 0.05, "integral {}", integral);
+    }
+
+    #[test]
+    fn transformed_light() {
+        let mut rng = rand_chacha::ChaCha20Rng::seed_from_u64(0xDEADBEEF);
+        let transform = Transform::scale(Vec3::new(2.0, 1.0, 0.5))
+            .then(&Transform::rotate_z(30.0))
+            .then(&Transform::translate(Vec3::new(0.5, 1.0, 0.0)));
+        let geometry = GeometricObject::from(Transformed::new(
+            xz_rect(-1.0, 1.0, -1.0, 1.0, 2.0),
+            transform,
+        ));
+        let mut lights = LightList::default();
+        lights.push(LightShape::from_geometry(&geometry).unwrap(), white_light());
+
+        let origin = Point(Vec3::default());
+        for _ in 0..100 {
+            let d = lights.sample_direction(&mut rng, &origin).unwrap();
+            assert!(lights.hit(&Ray::new(origin, d), 0.0, f64::MAX).is_some());
+            assert!(lights.direction_pdf(&origin, &d) > 0.0);
+        }
+
+        // the scale changes the solid angle, the pdf has to follow
+        let n = 200_000;
+        let total: f64 = (0..n)
+            .map(|_| {
+                let d = crate::core::math::random_unit_vector(&mut rng);
+                lights.direction_pdf(&origin, &d)
+            })
+            .sum();
+        let integral = total * 4.0 * std::f64::consts::PI / n as f64;
+        assert!((integral - 1.0).abs() < 0.05, "integral {}", integral);
+    }
+}
@@ -1,25 +1,58 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::Context;
+
+use rand::Rng;
 use serde::{Deserialize, Serialize};
 
 use super::{
-    bvh::{aabb::Aabb, bbox_tree::BboxTree},
-    core::Ray,
+    bvh::{
+        aabb::Aabb,
+        bbox_tree::{count_primitive, BboxTree, BuildOptions, TraversalCounters},
+    },
+    core::{Color, Point, Ray, Vec3},
     geometry::{
         hittable::{Geometry, HitRecord, Hittable},
+        medium::ConstantMedium,
         object::GeometricObject,
+        transform::Transform,
     },
     material::{
         material_type::{MaterialType, SceneMaterial},
         texture::loader::{TextureLoader, TextureManager},
+        Material,
     },
     skybox::SkyBox,
 };
 use crate::bvh::bbox_tree::BboxTreeWorkspace;
 
+mod instance;
+mod lights;
+pub mod obj;
+
+use instance::{InstanceLoadObject, SceneInstance};
+pub use instance::{PrototypeBuilder, PrototypeId};
+use lights::{LightList, LightShape};
+
+/// Start of a shadow ray, to step off the surface it leaves.
+const SHADOW_T_MIN: f64 = 0.001;
+/// Fraction of the distance to a light a shadow ray stops short of it.
+const LIGHT_GAP: f64 = 1e-4;
+
 pub struct SceneObject {
     geometry: GeometricObject,
     pub material: SceneMaterial,
 }
 
+/// Participating media are sampled by the integrator instead of being hit.
+pub struct SceneMedium {
+    medium: ConstantMedium,
+    pub material: SceneMaterial,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct SceneLoadObject {
     geometry: GeometricObject,
@@ -34,9 +67,33 @@ impl Geometry for SceneObject {
     fn bounding_box(&self) -> Option<Aabb> {
         self.geometry.bounding_box()
     }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.geometry.occluded(ray, t_min, t_max)
+    }
+
+    fn hit_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> Option<HitRecord> {
+        self.geometry.hit_counted(ray, t_min, t_max, counters)
+    }
+
+    fn occluded_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> bool {
+        self.geometry.occluded_counted(ray, t_min, t_max, counters)
+    }
 }
 
-impl<'a> Geometry for &'a SceneObject {
+impl Geometry for &SceneObject {
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
         (*self).hit(ray, t_min, t_max)
     }
@@ -44,6 +101,30 @@ impl<'a> Geometry for &'a SceneObject {
     fn bounding_box(&self) -> Option<Aabb> {
         (*self).bounding_box()
     }
+
+    fn occluded(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        (*self).occluded(ray, t_min, t_max)
+    }
+
+    fn hit_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> Option<HitRecord> {
+        (*self).hit_counted(ray, t_min, t_max, counters)
+    }
+
+    fn occluded_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> bool {
+        (*self).occluded_counted(ray, t_min, t_max, counters)
+    }
 }
 
 pub struct HitList<T> {
@@ -64,22 +145,59 @@ where
 {
     type Leaf = T;
     fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(&T, HitRecord)> {
+        self.hit_counted(ray, t_min, t_max, &mut None)
+    }
+}
+
+impl<T> HitList<T>
+where
+    for<'a> &'a T: Geometry,
+{
+    /// `hit`, counting every object tested as a primitive.
+    pub fn hit_counted(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> Option<(&T, HitRecord)> {
         let mut closest: Option<(&T, HitRecord)> = None;
 
         for obj in &self.objects {
+            count_primitive(counters);
             let t_closest = closest.as_ref().map(|(_, r)| r.t).unwrap_or(t_max);
-            if let Some(hit) = obj.hit(ray, t_min, t_closest) {
+            if let Some(hit) = obj.hit_counted(ray, t_min, t_closest, counters) {
                 closest = Some((obj, hit))
             }
         }
         closest
     }
+
+    /// Whether any object is hit, stopping at the first one found.
+    pub fn occluded(
+        &self,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+        counters: &mut Option<TraversalCounters>,
+    ) -> bool {
+        self.objects.iter().any(|obj| {
+            count_primitive(counters);
+            obj.occluded_counted(ray, t_min, t_max, counters)
+        })
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct SceneBuilder {
     skybox: SkyBox,
     objects: Vec<SceneLoadObject>,
+    #[serde(default)]
+    models: Vec<obj::ObjLoadObject>,
+    #[serde(default)]
+    prototypes: Vec<PrototypeBuilder>,
+    #[serde(default)]
+    instances: Vec<InstanceLoadObject>,
 }
 
 impl Default for SceneBuilder {
@@ -87,11 +205,28 @@ impl Default for SceneBuilder {
         Self {
             skybox: SkyBox::Above,
             objects: Default::default(),
+            models: Default::default(),
+            prototypes: Default::default(),
+            instances: Default::default(),
         }
     }
 }
 
 impl SceneBuilder {
+    /// Read a saved scene, resolving relative model paths against the
+    /// directory the scene file is in.
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<SceneBuilder> {
+        let path = path.as_ref();
+        let f = std::fs::File::open(path).with_context(|| format!("could not open {:?}", path))?;
+        let mut scene: SceneBuilder = serde_json::from_reader(std::io::BufReader::new(f))
+            .with_context(|| format!("parsing {:?}", path))?;
+        let base = path.parent().unwrap_or_else(|| Path::new(""));
+        for model in scene.models.iter_mut() {
+            model.path = base.join(&model.path);
+        }
+        Ok(scene)
+    }
+
     pub fn set_skybox(&mut self, skybox: SkyBox) -> &mut Self {
         self.skybox = skybox;
         self
@@ -108,14 +243,87 @@ impl SceneBuilder {
         };
         self.objects.push(obj);
     }
+
+    /// Reference a wavefront `.obj` file, it is not read until `finalize`.
+    /// A relative path is taken from the current directory.
+    pub fn add_obj<P: Into<PathBuf>>(
+        &mut self,
+        path: P,
+        material: Option<MaterialType<TextureLoader>>,
+    ) {
+        self.models.push(obj::ObjLoadObject {
+            path: path.into(),
+            material,
+        });
+    }
+
+    /// Register a group of objects that can be placed with `add_instance`.
+    pub fn add_prototype(&mut self, prototype: PrototypeBuilder) -> PrototypeId {
+        self.prototypes.push(prototype);
+        PrototypeId(self.prototypes.len() - 1)
+    }
+
+    /// Place a prototype in the scene, optionally replacing all of its materials.
+    ///
+    /// Emissive objects of the instance are sampled as lights, placed by
+    /// `transform`, when their shape can be.
+    pub fn add_instance<M: Into<MaterialType<TextureLoader>>>(
+        &mut self,
+        prototype: PrototypeId,
+        transform: Transform,
+        material: Option<M>,
+    ) {
+        self.instances.push(InstanceLoadObject {
+            prototype,
+            transform,
+            material: material.map(|m| m.into()),
+        });
+    }
+
     pub fn finalize(self) -> anyhow::Result<Scene> {
+        self.finalize_with(&BuildOptions::default())
+    }
+
+    /// Same as `finalize`, with control over how the scene's BVHs are built.
+    pub fn finalize_with(mut self, options: &BuildOptions) -> anyhow::Result<Scene> {
+        for model in std::mem::take(&mut self.models) {
+            for (mesh, material) in model.load()? {
+                self.add(mesh, material);
+            }
+        }
+
         let mut bounded_objects = Vec::new();
         let mut unbounded_objects = HitList::default();
+        let mut media = Vec::new();
+        let mut lights = LightList::default();
 
         let mut texture_manager = TextureManager::default();
 
         for load_obj in self.objects {
             let loaded_material = load_obj.material.load_texture(&mut texture_manager)?;
+            if let GeometricObject::ConstantMedium(medium) = load_obj.geometry {
+                if medium.boundary().contains_medium() {
+                    anyhow::bail!("the boundary of a medium can not be a medium");
+                }
+                media.push(SceneMedium {
+                    medium,
+                    material: loaded_material,
+                });
+                continue;
+            }
+            if load_obj.geometry.contains_medium() {
+                anyhow::bail!("media can not be nested inside other geometry");
+            }
+            if loaded_material.is_emissive() {
+                match LightShape::from_geometry(&load_obj.geometry) {
+                    Some(light) => lights.push(light, loaded_material.clone()),
+                    None => log::warn!(
+                        "emissive {} can not be sampled as a light, only paths that hit it \
+                         by chance will see its light",
+                        load_obj.geometry.kind()
+                    ),
+                }
+            }
             let scene_obj = SceneObject {
                 geometry: load_obj.geometry,
                 material: loaded_material,
@@ -128,11 +336,54 @@ impl SceneBuilder {
             }
         }
 
-        let tree = BboxTree::new(bounded_objects);
+        let prototypes = self
+            .prototypes
+            .into_iter()
+            .map(|p| p.finalize(&mut texture_manager, options).map(Arc::new))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut instances = Vec::with_capacity(self.instances.len());
+        let mut unsampled_lights = 0;
+        for load_instance in self.instances {
+            let prototype = match prototypes.get(load_instance.prototype.0) {
+                Some(p) => p.clone(),
+                None => anyhow::bail!(
+                    "instance refers to prototype {} but there are only {}",
+                    load_instance.prototype.0,
+                    prototypes.len()
+                ),
+            };
+            let material = load_instance
+                .material
+                .map(|m| m.load_texture(&mut texture_manager))
+                .transpose()?;
+            let instance = SceneInstance::new(prototype, load_instance.transform, material);
+            if instance.bounding_box().is_none() {
+                log::warn!(
+                    "instance of prototype {} is skipped, the prototype is empty",
+                    load_instance.prototype.0
+                );
+                continue;
+            }
+            unsampled_lights += instance.push_lights(&mut lights);
+            instances.push(instance);
+        }
+        if unsampled_lights > 0 {
+            log::warn!(
+                "{} emissive objects in instances can not be sampled as lights, only paths \
+                 that hit them by chance will see their light",
+                unsampled_lights
+            );
+        }
+
+        let tree = BboxTree::with_options(bounded_objects, options);
         Ok(Scene {
             skybox: self.skybox,
             objects: unbounded_objects,
             tree,
+            instances: BboxTree::with_options(instances, options),
+            media,
+            lights,
         })
     }
 }
@@ -141,11 +392,17 @@ pub struct Scene {
     pub skybox: SkyBox,
     objects: HitList<SceneObject>,
     tree: BboxTree<SceneObject>,
+    instances: BboxTree<SceneInstance>,
+    media: Vec<SceneMedium>,
+    lights: LightList,
 }
 
 pub struct WorkspaceScene<'a, 'b> {
     objects: &'a HitList<SceneObject>,
     tree: &'a BboxTree<SceneObject>,
+    instances: &'a BboxTree<SceneInstance>,
+    media: &'a [SceneMedium],
+    lights: &'a LightList,
     stack: &'b mut BboxTreeWorkspace,
 }
 
@@ -155,15 +412,99 @@ impl<'a, 'b> WorkspaceScene<'a, 'b> {
         ray: &Ray,
         t_min: f64,
         t_max: f64,
-    ) -> Option<(&SceneObject, HitRecord)> {
-        let closest = self.objects.hit(ray, t_min, t_max);
+    ) -> Option<(&'a SceneMaterial, HitRecord)> {
+        let closest = self
+            .objects
+            .hit_counted(ray, t_min, t_max, self.stack.counters_mut())
+            .map(|(obj, r)| (&obj.material, r));
+        let t_closest = closest.as_ref().map(|(_, r)| r.t).unwrap_or(t_max);
+        let closest = self
+            .tree
+            .hit_workspace(self.stack, ray, t_min, t_closest)
+            .map(|(obj, r)| (&obj.material, r))
+            .or(closest);
         let t_closest = closest.as_ref().map(|(_, r)| r.t).unwrap_or(t_max);
-        let new_closest = self.tree.hit_workspace(self.stack, ray, t_min, t_closest);
-        new_closest.or(closest)
+        self.instances
+            .hit_workspace_with(
+                self.stack,
+                ray,
+                t_min,
+                t_closest,
+                |inst, ray, t_min, t_max, counters| inst.hit_material(ray, t_min, t_max, counters),
+            )
+            .or(closest)
+    }
+
+    /// Whether any surface is hit in `[t_min, t_max]`, stopping at the first
+    /// one found. Media don't count, they are sampled separately.
+    pub fn occluded(&mut self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        self.objects
+            .occluded(ray, t_min, t_max, self.stack.counters_mut())
+            || self.tree.occluded_workspace(self.stack, ray, t_min, t_max)
+            || self
+                .instances
+                .occluded_workspace(self.stack, ray, t_min, t_max)
+    }
+
+    /// Sample a scattering event in any medium along the ray before `t_max`,
+    /// which should be the closest surface hit.
+    pub fn sample_medium<R: Rng>(
+        &self,
+        rng: &mut R,
+        ray: &Ray,
+        t_min: f64,
+        t_max: f64,
+    ) -> Option<(&'a SceneMaterial, HitRecord)> {
+        let mut closest: Option<(&'a SceneMaterial, HitRecord)> = None;
+        for m in self.media {
+            let t_closest = closest.as_ref().map(|(_, r)| r.t).unwrap_or(t_max);
+            if let Some(hit) = m.medium.sample_distance(rng, ray, t_min, t_closest) {
+                closest = Some((&m.material, hit))
+            }
+        }
+        closest
+    }
+
+    pub fn has_lights(&self) -> bool {
+        !self.lights.is_empty()
+    }
+
+    /// A direction from `origin` toward one of the scene's lights.
+    pub fn sample_light<R: Rng>(&self, rng: &mut R, origin: &Point) -> Option<Vec3> {
+        self.lights.sample_direction(rng, origin)
+    }
+
+    /// Light arriving along a ray aimed at one of the scene's lights, if
+    /// nothing blocks it on the way.
+    pub fn light_emission<R: Rng>(&mut self, rng: &mut R, ray: &Ray) -> Option<Color> {
+        let (material, r) = self.lights.hit(ray, SHADOW_T_MIN, f64::INFINITY)?;
+        // stop short of the light, its own object is in the scene too
+        let t_light = r.t * (1.0 - LIGHT_GAP);
+        if self.occluded(ray, SHADOW_T_MIN, t_light)
+            || self.sample_medium(rng, ray, SHADOW_T_MIN, r.t).is_some()
+        {
+            return None;
+        }
+        material.emitted(ray, &r)
+    }
+
+    /// Density of `sample_light` picking `direction` from `origin`.
+    pub fn light_pdf(&self, origin: &Point, direction: &Vec3) -> f64 {
+        self.lights.direction_pdf(origin, direction)
     }
 }
 
 impl Scene {
+    /// The tree over the scene's own bounded objects.
+    pub fn object_tree(&self) -> &BboxTree<SceneObject> {
+        &self.tree
+    }
+
+    /// The tree over the instances of prototypes.
+    pub fn instance_tree(&self) -> &BboxTree<SceneInstance> {
+        &self.instances
+    }
+
     pub fn workspace_scene<'a, 'b>(
         &'a self,
         hit_stack: &'b mut BboxTreeWorkspace,
@@ -171,20 +512,20 @@ impl Scene {
         WorkspaceScene {
             objects: &self.objects,
             tree: &self.tree,
+            instances: &self.instances,
+            media: &self.media,
+            lights: &self.lights,
             stack: hit_stack,
         }
     }
 }
 
 impl Hittable for Scene {
-    type Leaf = SceneObject;
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(&SceneObject, HitRecord)> {
-        let closest = self.objects.hit(ray, t_min, t_max);
-        let t_closest = closest.as_ref().map(|(_, r)| r.t).unwrap_or(t_max);
+    type Leaf = SceneMaterial;
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(&SceneMaterial, HitRecord)> {
         let mut stack = BboxTreeWorkspace::default();
         log::warn!("creating new workspace stack, this should be done in the caller");
-        let new_closest = self.tree.hit_workspace(&mut stack, ray, t_min, t_closest);
-
-        new_closest.or(closest)
+        self.workspace_scene(&mut stack)
+            .hit_workspace(ray, t_min, t_max)
     }
 }
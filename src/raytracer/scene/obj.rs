@@ -0,0 +1,452 @@
+use std::{
+    collections::HashMap,
+    io::BufRead,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{Color, Point, Vec3},
+    geometry::triangle::{MeshData, TriangleMesh},
+    material::{
+        dielectric::Dielectric, lambertian::Lambertian, lighting::DiffuseLight,
+        material_type::MaterialType, metal::Metal, texture::loader::TextureLoader,
+    },
+};
+
+const DEFAULT_MATERIAL: &str = "";
+
+/// A wavefront `.obj` file referenced from a saved scene.
+///
+/// When `material` is set it replaces every material from the `.mtl` files.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ObjLoadObject {
+    /// Relative to the scene file when read with `SceneBuilder::load`, the
+    /// `.mtl` files are relative to the `.obj`
+    pub path: PathBuf,
+    #[serde(default)]
+    pub material: Option<MaterialType<TextureLoader>>,
+}
+
+impl ObjLoadObject {
+    pub fn load(&self) -> anyhow::Result<Vec<(TriangleMesh, MaterialType<TextureLoader>)>> {
+        let ObjModel { groups, materials } = load_obj(&self.path)?;
+        groups
+            .into_iter()
+            .map(|(name, mesh)| {
+                let material = match &self.material {
+                    Some(m) => m.clone(),
+                    None => materials
+                        .get(&name)
+                        .map(|m| m.to_material())
+                        .unwrap_or_else(default_material),
+                };
+                let mesh = TriangleMesh::new(mesh)
+                    .with_context(|| format!("invalid mesh in {:?}", self.path))?;
+                Ok((mesh, material))
+            })
+            .collect()
+    }
+}
+
+fn default_material() -> MaterialType<TextureLoader> {
+    Lambertian::new(TextureLoader::solid(0.73, 0.73, 0.73)).into()
+}
+
+/// The subset of a `newmtl` block that we know how to map onto our materials.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct ObjMaterial {
+    pub kd: Option<Vec3>,
+    pub ks: Option<Vec3>,
+    pub ke: Option<Vec3>,
+    pub ns: Option<f64>,
+    pub ni: Option<f64>,
+    pub d: Option<f64>,
+    pub illum: Option<u32>,
+    pub map_kd: Option<PathBuf>,
+}
+
+fn is_black(v: &Option<Vec3>) -> bool {
+    v.map(|v| v.near_zero()).unwrap_or(true)
+}
+
+impl ObjMaterial {
+    pub fn to_material(&self) -> MaterialType<TextureLoader> {
+        if !is_black(&self.ke) {
+            return DiffuseLight::new(TextureLoader::solid_from_vec(self.ke.unwrap())).into();
+        }
+        let transparent = self.d.map(|d| d < 1.0).unwrap_or(false)
+            || matches!(self.illum, Some(4) | Some(6) | Some(7) | Some(9));
+        if transparent {
+            return Dielectric {
+                ir: self.ni.unwrap_or(1.5),
+            }
+            .into();
+        }
+        if !is_black(&self.ks) && (is_black(&self.kd) || self.illum == Some(3)) {
+            // phong exponent to a roughness, see "Microfacet Models for
+            // Refraction through Rough Surfaces" (Walter et al.)
+            let fuzz = self.ns.map(|ns| (2.0 / (ns + 2.0)).sqrt()).unwrap_or(0.0);
+            return Metal::new(Color(self.ks.unwrap()), Some(fuzz)).into();
+        }
+        let albedo = match &self.map_kd {
+            Some(p) => TextureLoader::ImagePath(p.clone()),
+            None => TextureLoader::solid_from_vec(
+                self.kd.unwrap_or_else(|| Vec3::new(0.73, 0.73, 0.73)),
+            ),
+        };
+        Lambertian::new(albedo).into()
+    }
+}
+
+/// One mesh per `usemtl` group, keyed by material name
+pub type MeshGroups = Vec<(String, MeshData)>;
+
+#[derive(Debug, Default)]
+pub struct ObjModel {
+    pub groups: MeshGroups,
+    pub materials: HashMap<String, ObjMaterial>,
+}
+
+pub fn load_obj<P: AsRef<Path>>(path: P) -> anyhow::Result<ObjModel> {
+    let path = path.as_ref();
+    let f = std::fs::File::open(path).with_context(|| format!("could not open {:?}", path))?;
+    let base = path.parent().unwrap_or_else(|| Path::new(""));
+    let (groups, libs) =
+        parse_obj(std::io::BufReader::new(f)).with_context(|| format!("parsing {:?}", path))?;
+
+    let mut materials = HashMap::new();
+    for lib in libs {
+        let lib_path = base.join(lib);
+        let f = std::fs::File::open(&lib_path)
+            .with_context(|| format!("could not open {:?}", lib_path))?;
+        let lib_base = lib_path.parent().unwrap_or_else(|| Path::new(""));
+        materials.extend(
+            parse_mtl(std::io::BufReader::new(f), lib_base)
+                .with_context(|| format!("parsing {:?}", lib_path))?,
+        );
+    }
+    Ok(ObjModel { groups, materials })
+}
+
+type VertexKey = (usize, Option<usize>, Option<usize>);
+
+#[derive(Default)]
+struct GroupBuilder {
+    lookup: HashMap<VertexKey, usize>,
+    keys: Vec<VertexKey>,
+    faces: Vec<[usize; 3]>,
+}
+
+impl GroupBuilder {
+    fn vertex(&mut self, key: VertexKey) -> usize {
+        let keys = &mut self.keys;
+        *self.lookup.entry(key).or_insert_with(|| {
+            keys.push(key);
+            keys.len() - 1
+        })
+    }
+
+    fn build(self, positions: &[Point], uvs: &[(f64, f64)], normals: &[Vec3]) -> MeshData {
+        // per vertex attributes are all or nothing for a mesh
+        let has_uv = self.keys.iter().all(|(_, vt, _)| vt.is_some());
+        let has_normal = self.keys.iter().all(|(_, _, vn)| vn.is_some());
+        MeshData {
+            vertices: self.keys.iter().map(|(v, _, _)| positions[*v]).collect(),
+            uvs: if has_uv {
+                self.keys
+                    .iter()
+                    .map(|(_, vt, _)| uvs[vt.unwrap()])
+                    .collect()
+            } else {
+                vec![]
+            },
+            normals: if has_normal {
+                self.keys
+                    .iter()
+                    .map(|(_, _, vn)| normals[vn.unwrap()])
+                    .collect()
+            } else {
+                vec![]
+            },
+            faces: self.faces,
+        }
+    }
+}
+
+fn parse_floats<'a>(line: usize, parts: impl Iterator<Item = &'a str>) -> anyhow::Result<Vec<f64>> {
+    parts
+        .map(|p| {
+            p.parse::<f64>()
+                .with_context(|| format!("line {}: invalid number `{}`", line, p))
+        })
+        .collect()
+}
+
+fn parse_vec3<'a>(line: usize, parts: impl Iterator<Item = &'a str>) -> anyhow::Result<Vec3> {
+    let v = parse_floats(line, parts)?;
+    if v.len() < 3 {
+        anyhow::bail!("line {}: expected 3 components, found {}", line, v.len());
+    }
+    Ok(Vec3::new(v[0], v[1], v[2]))
+}
+
+/// OBJ indices are 1-based, negative values count back from the end.
+fn resolve_index(line: usize, raw: &str, len: usize) -> anyhow::Result<usize> {
+    let idx = raw
+        .parse::<i64>()
+        .with_context(|| format!("line {}: invalid index `{}`", line, raw))?;
+    let resolved = if idx < 0 { len as i64 + idx } else { idx - 1 };
+    if resolved < 0 || resolved as usize >= len {
+        anyhow::bail!("line {}: index {} out of range ({})", line, idx, len);
+    }
+    Ok(resolved as usize)
+}
+
+/// Parse the geometry of an obj file, returning the triangulated groups and
+/// the referenced material libraries.
+pub fn parse_obj<R: BufRead>(reader: R) -> anyhow::Result<(MeshGroups, Vec<String>)> {
+    let mut positions = Vec::new();
+    let mut uvs = Vec::new();
+    let mut normals = Vec::new();
+    let mut libs = Vec::new();
+
+    let mut order: Vec<String> = Vec::new();
+    let mut groups: HashMap<String, GroupBuilder> = HashMap::new();
+    let mut current = DEFAULT_MATERIAL.to_string();
+
+    for (line_idx, line) in reader.lines().enumerate() {
+        let line_no = line_idx + 1;
+        let line = line?;
+        let line = line.split('#').next().unwrap_or("");
+        let mut parts = line.split_whitespace();
+        let keyword = match parts.next() {
+            Some(k) => k,
+            None => continue,
+        };
+        match keyword {
+            "v" => positions.push(Point(parse_vec3(line_no, parts)?)),
+            "vn" => normals.push(parse_vec3(line_no, parts)?),
+            "vt" => {
+                let uv = parse_floats(line_no, parts)?;
+                if uv.is_empty() {
+                    anyhow::bail!("line {}: texture coordinate without values", line_no);
+                }
+                uvs.push((uv[0], uv.get(1).cloned().unwrap_or(0.0)));
+            }
+            "f" => {
+                let mut face = Vec::new();
+                for vertex in parts {
+                    let mut idx = vertex.split('/');
+                    let v = resolve_index(line_no, idx.next().unwrap_or(""), positions.len())?;
+                    let vt = match idx.next() {
+                        Some(s) if !s.is_empty() => Some(resolve_index(line_no, s, uvs.len())?),
+                        _ => None,
+                    };
+                    let vn = match idx.next() {
+                        Some(s) if !s.is_empty() => Some(resolve_index(line_no, s, normals.len())?),
+                        _ => None,
+                    };
+                    face.push((v, vt, vn));
+                }
+                if face.len() < 3 {
+                    anyhow::bail!("line {}: face with {} vertices", line_no, face.len());
+                }
+                if !groups.contains_key(&current) {
+                    order.push(current.clone());
+                }
+                let group = groups.entry(current.clone()).or_default();
+                let indices = face
+                    .into_iter()
+                    .map(|k| group.vertex(k))
+                    .collect::<Vec<_>>();
+                // polygons are assumed convex, fan triangulate
+                for i in 1..indices.len() - 1 {
+                    group.faces.push([indices[0], indices[i], indices[i + 1]]);
+                }
+            }
+            "usemtl" => current = parts.collect::<Vec<_>>().join(" "),
+            "mtllib" => libs.extend(parts.map(|s| s.to_string())),
+            // groups, objects and smoothing groups don't change how we render
+            "o" | "g" | "s" | "l" | "p" => {}
+            other => log::debug!("line {}: ignoring obj statement `{}`", line_no, other),
+        }
+    }
+
+    let groups = order
+        .into_iter()
+        .map(|name| {
+            let mesh = groups
+                .remove(&name)
+                .unwrap()
+                .build(&positions, &uvs, &normals);
+            (name, mesh)
+        })
+        .collect();
+    Ok((groups, libs))
+}
+
+/// Parse a material library, texture maps are resolved relative to `base`.
+pub fn parse_mtl<R: BufRead>(
+    reader: R,
+    base: &Path,
+) -> anyhow::Result<HashMap<String, ObjMaterial>> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, ObjMaterial)> = None;
+
+    for (line_idx, line) in reader.lines().enumerate() {
+        let line_no = line_idx + 1;
+        let line = line?;
+        let line = line.split('#').next().unwrap_or("");
+        let mut parts = line.split_whitespace();
+        let keyword = match parts.next() {
+            Some(k) => k,
+            None => continue,
+        };
+        if keyword == "newmtl" {
+            if let Some((name, mat)) = current.take() {
+                materials.insert(name, mat);
+            }
+            current = Some((parts.collect::<Vec<_>>().join(" "), ObjMaterial::default()));
+            continue;
+        }
+        let mat = match current.as_mut() {
+            Some((_, m)) => m,
+            None => anyhow::bail!("line {}: `{}` before newmtl", line_no, keyword),
+        };
+        match keyword {
+            "Kd" => mat.kd = Some(parse_vec3(line_no, parts)?),
+            "Ks" => mat.ks = Some(parse_vec3(line_no, parts)?),
+            "Ke" => mat.ke = Some(parse_vec3(line_no, parts)?),
+            "Ns" => mat.ns = parse_floats(line_no, parts)?.first().cloned(),
+            "Ni" => mat.ni = parse_floats(line_no, parts)?.first().cloned(),
+            "d" => mat.d = parse_floats(line_no, parts)?.first().cloned(),
+            "Tr" => mat.d = parse_floats(line_no, parts)?.first().map(|tr| 1.0 - tr),
+            "illum" => {
+                let raw = parts.next().unwrap_or("");
+                mat.illum = Some(
+                    raw.parse()
+                        .with_context(|| format!("line {}: invalid illum `{}`", line_no, raw))?,
+                );
+            }
+            // options like `-bm 1.0` come before the filename
+            "map_Kd" => mat.map_kd = parts.last().map(|p| base.join(p)),
+            other => log::debug!("line {}: ignoring mtl statement `{}`", line_no, other),
+        }
+    }
+    if let Some((name, mat)) = current.take() {
+        materials.insert(name, mat);
+    }
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CUBE_FACE: &str = "
+mtllib cube.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+usemtl red
+f 1/1/1 2/2/1 3/3/1 4/4/1
+usemtl glass
+f -4 -3 -2
+";
+
+    #[test]
+    fn parse_quad_and_triangle() {
+        let (groups, libs) = parse_obj(CUBE_FACE.as_bytes()).unwrap();
+        assert_eq!(libs, vec!["cube.mtl".to_string()]);
+        assert_eq!(groups.len(), 2);
+
+        let (name, quad) = &groups[0];
+        assert_eq!(name, "red");
+        assert_eq!(quad.faces, vec![[0, 1, 2], [0, 2, 3]]);
+        assert_eq!(quad.vertices.len(), 4);
+        assert_eq!(quad.uvs.len(), 4);
+        assert_eq!(quad.normals, vec![Vec3::new(0.0, 0.0, 1.0); 4]);
+
+        let (name, tri) = &groups[1];
+        assert_eq!(name, "glass");
+        assert_eq!(tri.vertices[0], Point(Vec3::new(0.0, 0.0, 0.0)));
+        assert!(tri.uvs.is_empty());
+        assert!(tri.normals.is_empty());
+    }
+
+    #[test]
+    fn reject_out_of_range() {
+        let obj = "v 0 0 0\nv 1 0 0\nf 1 2 3\n";
+        assert!(parse_obj(obj.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn parse_materials() {
+        let mtl = "
+newmtl red
+Kd 0.65 0.05 0.05
+newmtl glass
+Ni 1.5
+d 0.0
+newmtl gold
+Kd 0 0 0
+Ks 1.0 0.8 0.3
+Ns 100
+newmtl lamp
+Ke 15 15 15
+newmtl earth
+Kd 1 1 1
+map_Kd -bm 1.0 earth.jpg
+";
+        let mats = parse_mtl(mtl.as_bytes(), Path::new("assets")).unwrap();
+        assert_eq!(mats.len(), 5);
+        assert!(matches!(
+            mats["red"].to_material(),
+            MaterialType::Lambertian(_)
+        ));
+        assert!(matches!(
+            mats["glass"].to_material(),
+            MaterialType::Dielectric(Dielectric { ir }) if ir == 1.5
+        ));
+        assert!(matches!(mats["gold"].to_material(), MaterialType::Metal(_)));
+        assert!(matches!(
+            mats["lamp"].to_material(),
+            MaterialType::DiffuseLight(_)
+        ));
+        match mats["earth"].to_material() {
+            MaterialType::Lambertian(l) => assert_eq!(
+                l.albedo,
+                TextureLoader::ImagePath(PathBuf::from("assets/earth.jpg"))
+            ),
+            _ => panic!("expected a lambertian texture"),
+        }
+    }
+
+    #[test]
+    fn model_paths_follow_the_scene_file() {
+        let dir = std::env::temp_dir().join(format!("obj_scene_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("tri.obj"), "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        let mut scene = crate::scene::SceneBuilder::default();
+        scene.add_obj("tri.obj", None);
+        std::fs::write(
+            dir.join("scene.json"),
+            serde_json::to_string(&scene).unwrap(),
+        )
+        .unwrap();
+
+        let loaded = crate::scene::SceneBuilder::load(dir.join("scene.json"));
+        let finalized = loaded.and_then(|s| s.finalize());
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(finalized.unwrap().object_tree().stats().items, 1);
+    }
+}
@@ -0,0 +1,176 @@
+use std::str::FromStr;
+
+use crate::core::{Color, Vec3};
+
+/// How radiance above 1.0 is squeezed into the displayable range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    /// Cut off anything brighter than 1.0
+    Clamp,
+    /// `L / (1 + L)` on luminance, never reaches white
+    Reinhard,
+    /// Reinhard, but luminance at the white point maps to 1.0
+    ExtendedReinhard,
+    /// Narkowicz's fit of the ACES filmic curve, per channel
+    Aces,
+}
+
+impl FromStr for ToneMapOperator {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "clamp" => Ok(ToneMapOperator::Clamp),
+            "reinhard" => Ok(ToneMapOperator::Reinhard),
+            "extended-reinhard" => Ok(ToneMapOperator::ExtendedReinhard),
+            "aces" => Ok(ToneMapOperator::Aces),
+            _ => anyhow::bail!(
+                "unknown tone map {:?}, expected clamp, reinhard, extended-reinhard or aces",
+                s
+            ),
+        }
+    }
+}
+
+/// Turns linear radiance into sRGB encoded values in `[0, 1]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ToneMap {
+    /// Exposure compensation, in stops
+    pub exposure: f64,
+    pub operator: ToneMapOperator,
+    /// Luminance that becomes white with `ExtendedReinhard`
+    pub white_point: f64,
+}
+
+impl Default for ToneMap {
+    fn default() -> Self {
+        ToneMap {
+            exposure: 0.0,
+            operator: ToneMapOperator::Clamp,
+            white_point: 4.0,
+        }
+    }
+}
+
+fn luminance(c: &Vec3) -> f64 {
+    0.2126 * c.x() + 0.7152 * c.y() + 0.0722 * c.z()
+}
+
+/// Scale the color so its luminance becomes `target`, keeping the hue.
+fn with_luminance(c: Vec3, target: f64) -> Vec3 {
+    let l = luminance(&c);
+    if l <= 0.0 {
+        return Vec3::default();
+    }
+    c.scale(target / l)
+}
+
+fn aces(x: f64) -> f64 {
+    let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+    (x * (a * x + b)) / (x * (c * x + d) + e)
+}
+
+/// The sRGB transfer curve, from linear to encoded.
+pub fn srgb_encode(x: f64) -> f64 {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl ToneMap {
+    /// Map linear radiance to a linear value in `[0, 1]`.
+    pub fn map_linear(&self, c: Color) -> Color {
+        let c = c.0.scale(2f64.powf(self.exposure)).map(|x| x.max(0.0));
+        let mapped = match self.operator {
+            ToneMapOperator::Clamp => c,
+            ToneMapOperator::Reinhard => {
+                let l = luminance(&c);
+                with_luminance(c, l / (1.0 + l))
+            }
+            ToneMapOperator::ExtendedReinhard => {
+                let l = luminance(&c);
+                let white2 = self.white_point * self.white_point;
+                with_luminance(c, l * (1.0 + l / white2) / (1.0 + l))
+            }
+            ToneMapOperator::Aces => c.map(aces),
+        };
+        Color(mapped.map(|x| x.clamp(0.0, 1.0)))
+    }
+
+    /// Map linear radiance to sRGB encoded values, ready for an 8-bit image.
+    pub fn apply(&self, c: Color) -> Color {
+        Color(self.map_linear(c).0.map(srgb_encode))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grey(v: f64) -> Color {
+        Color(Vec3::new(v, v, v))
+    }
+
+    fn operator(operator: ToneMapOperator) -> ToneMap {
+        ToneMap {
+            operator,
+            ..ToneMap::default()
+        }
+    }
+
+    #[test]
+    fn srgb_curve_end_points() {
+        assert_eq!(srgb_encode(0.0), 0.0);
+        assert!((srgb_encode(1.0) - 1.0).abs() < 1e-12);
+        // 18% grey lands a bit below the middle
+        assert!((srgb_encode(0.18) - 0.4613).abs() < 1e-3);
+    }
+
+    #[test]
+    fn exposure_doubles_per_stop() {
+        let t = ToneMap {
+            exposure: 1.0,
+            ..ToneMap::default()
+        };
+        assert_eq!(t.map_linear(grey(0.25)), grey(0.5));
+    }
+
+    #[test]
+    fn operators_stay_in_range() {
+        for op in &[
+            ToneMapOperator::Clamp,
+            ToneMapOperator::Reinhard,
+            ToneMapOperator::ExtendedReinhard,
+            ToneMapOperator::Aces,
+        ] {
+            let t = operator(*op);
+            for v in &[0.0, 0.5, 1.0, 15.0, 1000.0] {
+                let c = t.apply(Color(Vec3::new(*v, v * 0.5, 0.0)));
+                for d in 0..3 {
+                    assert!((0.0..=1.0).contains(&c.0[d]), "{:?} {} {:?}", op, v, c);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn extended_reinhard_white_point() {
+        let t = operator(ToneMapOperator::ExtendedReinhard);
+        let c = t.map_linear(grey(t.white_point));
+        assert!((c.0.x() - 1.0).abs() < 1e-9);
+        // plain reinhard keeps bright lights distinguishable
+        let r = operator(ToneMapOperator::Reinhard);
+        assert!(r.map_linear(grey(15.0)).0.x() < r.map_linear(grey(30.0)).0.x());
+    }
+
+    #[test]
+    fn parse_operator() {
+        assert_eq!(
+            "extended-reinhard".parse::<ToneMapOperator>().unwrap(),
+            ToneMapOperator::ExtendedReinhard
+        );
+        assert!("filmic".parse::<ToneMapOperator>().is_err());
+    }
+}
@@ -4,8 +4,10 @@ use raytracer::{
     camera::{Camera, CameraBuilder, CameraPosition},
     core::{math::random_real, Color, Point, Vec3},
     geometry::{
+        plane::Plane,
         rect::{xy_rect, xz_rect, yz_rect, RectBox},
         sphere::Sphere,
+        transform::{Transform, Transformed},
     },
     material::{
         dielectric::Dielectric,
@@ -44,17 +46,23 @@ pub fn create_cornell_box() -> anyhow::Result<Scene> {
     );
 
     scene.add(
-        RectBox::new(
-            Point(Vec3::new(130.0, 0.0, 65.0)),
-            Point(Vec3::new(295.0, 165.0, 230.0)),
+        Transformed::new(
+            RectBox::new(
+                Point(Vec3::new(0.0, 0.0, 0.0)),
+                Point(Vec3::new(165.0, 330.0, 165.0)),
+            ),
+            Transform::rotate_y(15.0).then(&Transform::translate(Vec3::new(265.0, 0.0, 295.0))),
         ),
         white.clone(),
     );
 
     scene.add(
-        RectBox::new(
-            Point(Vec3::new(265.0, 0.0, 295.0)),
-            Point(Vec3::new(430.0, 330.0, 460.0)),
+        Transformed::new(
+            RectBox::new(
+                Point(Vec3::new(0.0, 0.0, 0.0)),
+                Point(Vec3::new(165.0, 165.0, 165.0)),
+            ),
+            Transform::rotate_y(-18.0).then(&Transform::translate(Vec3::new(130.0, 0.0, 65.0))),
         ),
         white,
     );
@@ -126,8 +134,7 @@ pub fn create_box_light() -> anyhow::Result<Scene> {
     scene.finalize()
 }
 pub fn render_saved(args: &argparse::RenderSaved) -> Result<()> {
-    let f = std::fs::File::open(args.scene_input.as_str())?;
-    let scene: SceneBuilder = serde_json::from_reader(f)?;
+    let scene = SceneBuilder::load(args.scene_input.as_str())?;
     let scene = scene.finalize()?;
     let (camera, pos) = default_camera(&args.camera)?;
     render_scene(&args.config, &scene, &camera, &pos)
@@ -218,7 +225,8 @@ pub fn default_camera(args: &CameraSettings) -> Result<(Camera, CameraPosition)>
         .focal_length(args.camera_focal_length)
         .aperture(args.camera_aperture)
         .width(args.width)
-        .aspect_ratio(args.camera_aspect_ratio.ratio());
+        .aspect_ratio(args.camera_aspect_ratio.ratio())
+        .shutter(args.shutter_open, args.shutter_close);
 
     let camera = camera.build()?;
     let mut pos = CameraPosition::look_at(
@@ -237,15 +245,13 @@ pub fn create_ground_checker(scene: &mut SceneBuilder) {
         TextureLoader::solid(0.9, 0.9, 0.9),
     );
     let mat_ground = Lambertian::new(ground_texture);
-    let rect = 30.0;
-    scene.add(xz_rect(-rect, rect, -rect, rect, -0.0001), mat_ground);
-    // scene.add(
-    //     Sphere {
-    //         center: Point(Vec3::new(0.0, -1000.0, 0.0)),
-    //         radius: 1000.0,
-    //     },
-    //     mat_ground,
-    // );
+    scene.add(
+        Plane::new(
+            Point(Vec3::new(0.0, -0.0001, 0.0)),
+            Vec3::new(0.0, 1.0, 0.0),
+        ),
+        mat_ground,
+    );
 }
 
 pub fn create_fancy_ground(scene: &mut SceneBuilder) {